@@ -0,0 +1,247 @@
+use crate::config::{self, Level};
+use crate::{cli, flikisdining, health, history, metrics, search};
+use chrono::{DateTime, Utc};
+use serenity::{
+    all::{
+        Command, CommandInteraction, CommandOptionType, CreateCommand, CreateCommandOption,
+        EditInteractionResponse, ResolvedOption, ResolvedValue,
+    },
+    builder::{CreateAttachment, CreateEmbed, CreateEmbedFooter},
+    http::Http,
+    prelude::Context,
+};
+use tracing::{error, info};
+
+/// how many weeks of fetches `/admin diag` lists
+const DIAG_WEEKS: usize = 8;
+
+/// discord caps embed field values at 1024 characters
+const MAX_FIELD_LENGTH: usize = 1024;
+
+/// Registers `/admin` with discord, so it shows up in every server the bot is in.
+pub async fn register(http: &Http) {
+    let command = CreateCommand::new("admin")
+        .description("Bot diagnostics and maintenance")
+        .add_option(CreateCommandOption::new(
+            CommandOptionType::SubCommand,
+            "diag",
+            "Show the config, cache, fetches, search index and uptime",
+        ))
+        .add_option(
+            CreateCommandOption::new(
+                CommandOptionType::SubCommandGroup,
+                "cache",
+                "The FlikIsDining HTTP cache",
+            )
+            .add_sub_option(CreateCommandOption::new(
+                CommandOptionType::SubCommand,
+                "clear",
+                "Empty the HTTP cache",
+            )),
+        )
+        .add_option(
+            CreateCommandOption::new(
+                CommandOptionType::SubCommand,
+                "refetch",
+                "Fetch a week again, skipping the cache",
+            )
+            .add_sub_option(
+                CreateCommandOption::new(
+                    CommandOptionType::String,
+                    "week",
+                    "A day in the week (yyyy-mm-dd, tmr, friday, ...)",
+                )
+                .required(true),
+            ),
+        );
+
+    if let Err(why) = Command::create_global_command(http, command).await {
+        error!("Error registering /admin: {:?}", why);
+    }
+}
+
+/// The level a subcommand needs, looking is for moderators and changing things for admins.
+pub fn level(command: &CommandInteraction) -> Level {
+    match command.data.options().as_slice() {
+        [ResolvedOption { name: "diag", .. }] => Level::Moderator,
+        _ => Level::Admin,
+    }
+}
+
+/// `3d 4h`, `12m`, `40s`, ...
+fn format_duration(duration: chrono::Duration) -> String {
+    let seconds = duration.num_seconds().max(0);
+
+    match seconds {
+        0..=59 => format!("{}s", seconds),
+        60..=3599 => format!("{}m", seconds / 60),
+        3600..=86399 => format!("{}h {}m", seconds / 3600, seconds % 3600 / 60),
+        _ => format!("{}d {}h", seconds / 86400, seconds % 86400 / 3600),
+    }
+}
+
+fn ago(time: DateTime<Utc>) -> String {
+    format!("{} ago", format_duration(Utc::now() - time))
+}
+
+/// The last few weeks that were fetched, one line each.
+fn fetches() -> String {
+    let weeks = health::week_fetches();
+    if weeks.is_empty() {
+        return "Nothing fetched yet".to_owned();
+    }
+
+    let mut lines = weeks
+        .iter()
+        .rev()
+        .take(DIAG_WEEKS)
+        .map(|(monday, school, fetch)| match &fetch.error {
+            None => format!("`{}` {}: ✅ {}", monday, school, ago(fetch.at)),
+            Some(why) => format!(
+                "`{}` {}: ❌ {}, {}",
+                monday,
+                school,
+                why.chars().take(80).collect::<String>(),
+                ago(fetch.at)
+            ),
+        })
+        .collect::<Vec<String>>();
+
+    // drop the oldest weeks until it fits
+    while lines.join("\n").len() > MAX_FIELD_LENGTH {
+        lines.pop();
+    }
+
+    lines.join("\n")
+}
+
+fn diag() -> EditInteractionResponse {
+    let start = Utc::now();
+    let config = config::get();
+
+    let gateway = match health::connected() {
+        Some(true) => "Connected",
+        Some(false) => "Disconnected",
+        None => "Not running",
+    };
+
+    let cache = if config.cache.enabled {
+        format!(
+            "{} responses, {} hits / {} misses",
+            flikisdining::cache_entries(),
+            metrics::CACHE.with_label_values(&["hit"]).get(),
+            metrics::CACHE.with_label_values(&["miss"]).get()
+        )
+    } else {
+        "Off".to_owned()
+    };
+
+    let index = match search::last_index() {
+        Some((documents, at)) => format!("{} dishes, built {}", documents, ago(at)),
+        None => "No searches yet".to_owned(),
+    };
+
+    let embed = CreateEmbed::new()
+        .title("🛠️ Diagnostics")
+        .field("Version", env!("CARGO_PKG_VERSION"), true)
+        .field(
+            "Uptime",
+            format_duration(Utc::now() - health::started()),
+            true,
+        )
+        .field("Gateway", gateway, true)
+        .field("HTTP cache", cache, true)
+        .field("Search index", index, true)
+        .field(
+            "Menu history",
+            format!("{} days", history::days().len()),
+            true,
+        )
+        .field("Last fetches", fetches(), false)
+        .footer(CreateEmbedFooter::new(
+            (Utc::now() - start).num_milliseconds().to_string() + " ms",
+        ))
+        .color(0xEE8B2F);
+
+    let response = EditInteractionResponse::new().embed(embed);
+
+    // the resolved config is too long for an embed, so it's attached
+    match config.describe() {
+        Ok(text) => {
+            response.new_attachment(CreateAttachment::bytes(text.into_bytes(), "config.toml"))
+        }
+        Err(why) => {
+            error!("Error describing config: {:?}", why);
+            response
+        }
+    }
+}
+
+fn clear_cache() -> EditInteractionResponse {
+    if !config::get().cache.enabled {
+        return EditInteractionResponse::new().content("The HTTP cache is off.");
+    }
+
+    let entries = flikisdining::clear_cache();
+    info!("Cleared {} cached responses", entries);
+
+    EditInteractionResponse::new().content(format!("Cleared {} cached responses.", entries))
+}
+
+async fn refetch(week: &str) -> EditInteractionResponse {
+    let date = cli::resolve_date(week);
+
+    let content = match flikisdining::refetch_week(date).await {
+        Ok(response) => format!(
+            "Refetched the week of {}: {} days, {} dishes (last updated {})",
+            date.format("%b %-d"),
+            response.days.len(),
+            response
+                .days
+                .iter()
+                .map(|day| day.menu_items.len())
+                .sum::<usize>(),
+            response.last_updated.as_deref().unwrap_or("unknown")
+        ),
+        Err(why) => format!("Refetch failed: {}", why.report()),
+    };
+
+    EditInteractionResponse::new().content(content)
+}
+
+pub async fn handle(context: Context, command: CommandInteraction) {
+    // fetching can take a while, so answer "thinking..." first
+    if let Err(why) = command.defer_ephemeral(&context.http).await {
+        error!("Error deferring /admin: {:?}", why);
+        return;
+    }
+
+    let options = command.data.options();
+    let response = match options.as_slice() {
+        [ResolvedOption { name: "diag", .. }] => diag(),
+        [ResolvedOption {
+            name: "cache",
+            value: ResolvedValue::SubCommandGroup(options),
+            ..
+        }] if matches!(options.as_slice(), [ResolvedOption { name: "clear", .. }]) => clear_cache(),
+        [ResolvedOption {
+            name: "refetch",
+            value: ResolvedValue::SubCommand(options),
+            ..
+        }] => match options.as_slice() {
+            [ResolvedOption {
+                name: "week",
+                value: ResolvedValue::String(week),
+                ..
+            }] => refetch(week).await,
+            _ => EditInteractionResponse::new().content("Usage: `/admin refetch <week>`"),
+        },
+        _ => EditInteractionResponse::new().content("Unknown /admin command"),
+    };
+
+    info!("Ran /admin for {}", command.user.name);
+
+    if let Err(why) = command.edit_response(&context.http, response).await {
+        error!("Error responding to /admin: {:?}", why);
+    }
+}
@@ -0,0 +1,140 @@
+use crate::flikisdining::{self, FetchError, FlikIsDiningDay, FlikIsDiningMenuItem};
+use crate::search::{self, SearchError, SearchResults};
+use crate::{calendar, config, feed, health, render, shutdown};
+use axum::{
+    extract::{Path, Query},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::net::SocketAddr;
+use thiserror::Error;
+use tracing::{error, info};
+
+#[derive(Error, Debug)]
+enum ApiError {
+    #[error("Invalid date `{0}`, expected yyyy-mm-dd")]
+    InvalidDate(String),
+
+    #[error("Missing search query, expected ?q=")]
+    MissingQuery,
+
+    #[error(transparent)]
+    Fetch(#[from] FetchError),
+
+    #[error(transparent)]
+    Search(#[from] SearchError),
+
+    #[error("{0}")]
+    Render(String),
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status = match &self {
+            ApiError::InvalidDate(_) | ApiError::MissingQuery => StatusCode::BAD_REQUEST,
+            ApiError::Fetch(FetchError::NoLunchForDate(_) | FetchError::MenuNotPublished(_)) => {
+                StatusCode::NOT_FOUND
+            }
+            ApiError::Search(SearchError::InvalidQuery(_)) => StatusCode::BAD_REQUEST,
+            ApiError::Fetch(_) => StatusCode::BAD_GATEWAY,
+            ApiError::Search(_) | ApiError::Render(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+
+        if status.is_server_error() {
+            error!("API error: {:?}", self);
+        }
+
+        (
+            status,
+            Json(serde_json::json!({ "error": self.to_string() })),
+        )
+            .into_response()
+    }
+}
+
+#[derive(Deserialize)]
+struct SearchParams {
+    q: Option<String>,
+}
+
+/// Parses a `yyyy-mm-dd` path segment into midday at the school on that day.
+fn parse_date(date: &str) -> Result<DateTime<Utc>, ApiError> {
+    config::get()
+        .parse_date(date)
+        .ok_or_else(|| ApiError::InvalidDate(date.to_owned()))
+}
+
+/// `GET /days/{date}`, the menu items for a single day
+async fn day(Path(date): Path<String>) -> Result<Json<Vec<FlikIsDiningMenuItem>>, ApiError> {
+    let date = parse_date(&date)?;
+    Ok(Json(flikisdining::fetch_lunch(date).await?))
+}
+
+/// `GET /weeks/{date}`, every day in the week containing the date
+async fn week(Path(date): Path<String>) -> Result<Json<Vec<FlikIsDiningDay>>, ApiError> {
+    let date = parse_date(&date)?;
+    Ok(Json(flikisdining::fetch_week_lunch(date).await?))
+}
+
+/// `GET /weeks/{date}/image.png`, the week as a rendered grid
+async fn week_image(Path(date): Path<String>) -> Result<impl IntoResponse, ApiError> {
+    let days = flikisdining::fetch_week_lunch(parse_date(&date)?).await?;
+    let image = tokio::task::spawn_blocking(move || render::render_week(&days))
+        .await
+        .map_err(|why| ApiError::Render(why.to_string()))?
+        .map_err(ApiError::Render)?;
+
+    Ok(([(header::CONTENT_TYPE, "image/png")], image))
+}
+
+/// `GET /search?q=`, same as "when will we have"
+async fn search(Query(params): Query<SearchParams>) -> Result<Json<SearchResults>, ApiError> {
+    let query = params
+        .q
+        .map(|q| q.trim().to_lowercase())
+        .filter(|q| !q.is_empty())
+        .ok_or(ApiError::MissingQuery)?;
+
+    Ok(Json(search::search(&config::get().school, &query).await?))
+}
+
+/// `GET /calendar.ics`, an all-day event for every upcoming school day
+async fn calendar() -> Result<impl IntoResponse, ApiError> {
+    Ok((
+        [(header::CONTENT_TYPE, "text/calendar; charset=utf-8")],
+        calendar::build().await?,
+    ))
+}
+
+/// `GET /feed.atom`, an entry for every served day
+async fn feed() -> Result<impl IntoResponse, ApiError> {
+    Ok((
+        [(header::CONTENT_TYPE, "application/atom+xml; charset=utf-8")],
+        feed::build().await?,
+    ))
+}
+
+pub fn router() -> Router {
+    Router::new()
+        .route("/days/{date}", get(day))
+        .route("/weeks/{date}", get(week))
+        .route("/weeks/{date}/image.png", get(week_image))
+        .route("/search", get(search))
+        .route("/calendar.ics", get(calendar))
+        .route("/feed.atom", get(feed))
+        .merge(health::router())
+}
+
+/// Serves the JSON API until the process exits.
+pub async fn serve(addr: SocketAddr) -> std::io::Result<()> {
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    info!("HTTP API listening on {}", addr);
+
+    axum::serve(listener, router())
+        .with_graceful_shutdown(shutdown::signal())
+        .await
+}
@@ -0,0 +1,203 @@
+use crate::config;
+use crate::flikisdining::{self, FetchError, FlikIsDiningDay};
+use crate::reply::{self, Reply};
+use chrono::{NaiveDate, Utc};
+use serenity::{builder::CreateAttachment, model::prelude::Message, prelude::Context};
+use tokio::task::JoinSet;
+use tracing::{error, warn};
+
+/// Escapes text for an iCalendar property value (RFC 5545 3.3.11).
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+/// Folds a content line so no line is longer than 75 bytes (RFC 5545 3.1).
+fn fold(line: &str) -> String {
+    let mut folded = String::new();
+    let mut length = 0;
+
+    for c in line.chars() {
+        if length + c.len_utf8() > 75 {
+            folded.push_str("\r\n ");
+            length = 1;
+        }
+
+        folded.push(c);
+        length += c.len_utf8();
+    }
+
+    folded + "\r\n"
+}
+
+/// One all-day event for a served day, or nothing if there's no menu.
+fn event(day: &FlikIsDiningDay, stamp: &str) -> Option<String> {
+    let date = NaiveDate::parse_from_str(&day.date, "%Y-%m-%d").ok()?;
+    let foods = day
+        .menu_items
+        .iter()
+        .filter_map(|item| item.food.as_ref().map(|food| (item.bold, food)))
+        .collect::<Vec<_>>();
+
+    // the main entrée is the first bold item, or just the first item
+    let (_, main) = foods.iter().find(|(bold, _)| *bold).or(foods.first())?;
+
+    let description = foods
+        .iter()
+        .map(|(_, food)| food.name.as_str())
+        .collect::<Vec<&str>>()
+        .join("\n");
+
+    let lines = [
+        "BEGIN:VEVENT".to_owned(),
+        format!("UID:{}@lunchbotv2", date.format("%Y%m%d")),
+        format!("DTSTAMP:{}", stamp),
+        format!("DTSTART;VALUE=DATE:{}", date.format("%Y%m%d")),
+        format!(
+            "DTEND;VALUE=DATE:{}",
+            (date + chrono::Duration::days(1)).format("%Y%m%d")
+        ),
+        format!("SUMMARY:{}", escape(&main.name)),
+        format!("DESCRIPTION:{}", escape(&description)),
+        "TRANSP:TRANSPARENT".to_owned(),
+        "END:VEVENT".to_owned(),
+    ];
+
+    Some(lines.iter().map(|line| fold(line)).collect())
+}
+
+/// Builds an `.ics` calendar with an event for every school day in the next
+/// `calendar_weeks` weeks. Weeks that can't be fetched are left out, so
+/// calendar apps keep the rest, it's only an error if none could be.
+pub async fn build() -> Result<String, FetchError> {
+    let mut set = JoinSet::new();
+    for n in 0..config::get().calendar_weeks {
+        let date = Utc::now() + chrono::Duration::weeks(n);
+        set.spawn(async move { (date, flikisdining::fetch_week_lunch(date).await) });
+    }
+
+    let mut days: Vec<FlikIsDiningDay> = Vec::new();
+    let mut failed: Option<FetchError> = None;
+    while let Some(res) = set.join_next().await {
+        match res {
+            Ok((_, Ok(week))) => days.extend(week),
+            Ok((date, Err(why))) => {
+                warn!(
+                    week = %date.format("%Y-%m-%d"),
+                    error = ?why,
+                    "Leaving a week out of the calendar"
+                );
+                failed = Some(why);
+            }
+            Err(why) => error!("Error joining calendar fetch: {:?}", why),
+        }
+    }
+
+    if let Some(why) = failed.filter(|_| days.is_empty()) {
+        return Err(why);
+    }
+
+    days.sort_by(|a, b| a.date.cmp(&b.date));
+    days.dedup_by(|a, b| a.date == b.date);
+
+    let stamp = Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+    let mut calendar = [
+        "BEGIN:VCALENDAR",
+        "VERSION:2.0",
+        "PRODID:-//lunchbotv2//Lunch Menu//EN",
+        "CALSCALE:GREGORIAN",
+        "METHOD:PUBLISH",
+        "X-WR-CALNAME:Lunch",
+    ]
+    .iter()
+    .map(|line| fold(line))
+    .collect::<String>();
+
+    for day in &days {
+        if let Some(event) = event(day, &stamp) {
+            calendar.push_str(&event);
+        }
+    }
+
+    calendar.push_str(&fold("END:VCALENDAR"));
+    Ok(calendar)
+}
+
+pub async fn handle(context: Context, msg: Message) {
+    // ignore bots
+    if msg.author.bot {
+        return;
+    }
+
+    let calendar = match build().await {
+        Ok(calendar) => calendar,
+        Err(why) => {
+            if let Err(why) = reply::send(&context, &msg, Reply::new().content(why.report())).await
+            {
+                error!("Error sending message: {:?}", why);
+            }
+
+            return;
+        }
+    };
+
+    let message = Reply::new()
+        .content(format!(
+            "📅 Lunch for the next {} weeks, import it into your calendar app",
+            config::get().calendar_weeks
+        ))
+        .add_file(CreateAttachment::bytes(calendar.into_bytes(), "lunch.ics"));
+
+    if let Err(why) = reply::send(&context, &msg, message).await {
+        error!("Error sending message: {:?}", why);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{escape, fold};
+
+    #[test]
+    fn escapes_text() {
+        assert_eq!(
+            escape("Mac, Cheese; \\ Peas\nCorn"),
+            "Mac\\, Cheese\\; \\\\ Peas\\nCorn"
+        );
+    }
+
+    #[test]
+    fn leaves_short_lines_alone() {
+        assert_eq!(fold("SUMMARY:Pizza"), "SUMMARY:Pizza\r\n");
+    }
+
+    #[test]
+    fn folds_at_75_octets() {
+        let line = "a".repeat(160);
+        let folded = fold(&line);
+
+        let lines = folded
+            .trim_end_matches("\r\n")
+            .split("\r\n")
+            .collect::<Vec<&str>>();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0].len(), 75);
+        assert_eq!(lines[1].len(), 75);
+        assert!(lines[1].starts_with(' '));
+        assert_eq!(folded.replace("\r\n ", ""), line + "\r\n");
+    }
+
+    #[test]
+    fn folds_multibyte_characters_whole() {
+        // 74 bytes, then a 2 byte é that doesn't fit, then 3 byte €s
+        let line = format!("{}é{}", "a".repeat(74), "€".repeat(30));
+        let folded = fold(&line);
+
+        for line in folded.trim_end_matches("\r\n").split("\r\n") {
+            assert!(line.len() <= 75, "{} bytes", line.len());
+        }
+        assert!(folded.starts_with(&format!("{}\r\n é", "a".repeat(74))));
+        assert_eq!(folded.replace("\r\n ", ""), line + "\r\n");
+    }
+}
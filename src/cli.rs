@@ -0,0 +1,217 @@
+use crate::flikisdining::{self, FlikIsDiningDay, FlikIsDiningMenuItem};
+use crate::{api, config, lunch, metrics, render, search, store, warm};
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::Serialize;
+use std::net::SocketAddr;
+
+/// where `serve` listens if `http_bind` isn't set
+const DEFAULT_BIND: &str = "127.0.0.1:8080";
+
+const USAGE: &str = "Usage: lunchbotv2 [command] [--json] [--png]
+
+Commands:
+  (none)            run the discord bot
+  menu [date]       print the menu for a day (yyyy-mm-dd, tmr, friday, ...)
+  week [date]       print the menu for the week containing a day
+                    (--png saves it as an image instead)
+  search <query>    search the next few weeks, like \"when will we have\"
+  serve             run the HTTP API without the discord bot
+  help              show this message";
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Bot,
+    Menu { date: String, json: bool },
+    Week { date: String, json: bool, png: bool },
+    Search { query: String, json: bool },
+    Serve,
+    Help,
+}
+
+/// Parses the command line arguments (without the program name).
+pub fn parse(args: impl Iterator<Item = String>) -> Result<Command, String> {
+    let mut json = false;
+    let mut png = false;
+    let mut words: Vec<String> = Vec::new();
+
+    for arg in args {
+        match arg.as_str() {
+            "--json" => json = true,
+            "--png" => png = true,
+            "-h" | "--help" => return Ok(Command::Help),
+            flag if flag.starts_with("--") => return Err(format!("Unknown flag {}", flag)),
+            _ => words.push(arg),
+        }
+    }
+
+    let Some((command, rest)) = words.split_first() else {
+        return Ok(Command::Bot);
+    };
+    let rest = rest.join(" ").to_lowercase();
+
+    match command.as_str() {
+        "menu" => Ok(Command::Menu { date: rest, json }),
+        "week" => Ok(Command::Week {
+            date: rest,
+            json,
+            png,
+        }),
+        "search" if rest.is_empty() => Err("search needs a query".to_owned()),
+        "search" => Ok(Command::Search { query: rest, json }),
+        "serve" => Ok(Command::Serve),
+        "help" => Ok(Command::Help),
+        command => Err(format!("Unknown command {}", command)),
+    }
+}
+
+/// Resolves a date argument, either `yyyy-mm-dd` or the same words `what lunch` understands.
+pub fn resolve_date(date: &str) -> DateTime<Utc> {
+    config::get()
+        .parse_date(date)
+        .unwrap_or_else(|| lunch::resolve_date(date).1)
+}
+
+fn print_json(value: &impl Serialize) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(value).map_err(|why| why.to_string())?;
+    println!("{}", json);
+    Ok(())
+}
+
+fn format_items(items: &[FlikIsDiningMenuItem]) -> String {
+    items
+        .iter()
+        .filter_map(|item| item.food.as_ref())
+        .map(|food| {
+            let calories = food
+                .rounded_nutrition_info
+                .as_ref()
+                .and_then(|info| info.calories)
+                .map(|calories| format!(" - {} cals", calories))
+                .unwrap_or_default();
+
+            format!("  {} {}{}", food.name, food.badges(), calories)
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+fn format_day(day: &FlikIsDiningDay) -> String {
+    let title = NaiveDate::parse_from_str(&day.date, "%Y-%m-%d")
+        .map(|date| date.format("%A, %b %-d").to_string())
+        .unwrap_or(day.date.clone());
+
+    if day.menu_items.is_empty() {
+        format!("{}\n  (no lunch)", title)
+    } else {
+        format!("{}\n{}", title, format_items(&day.menu_items))
+    }
+}
+
+/// Runs a CLI command. `Command::Bot` is handled by `main`.
+pub async fn run(command: Command) -> Result<(), String> {
+    match command {
+        Command::Bot => unreachable!("the bot is started from main"),
+        Command::Help => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        Command::Menu { date, json } => {
+            let date = resolve_date(&date);
+            let items = flikisdining::fetch_lunch(date)
+                .await
+                .map_err(|why| why.to_string())?;
+
+            if json {
+                return print_json(&items);
+            }
+
+            println!(
+                "Lunch for {}\n{}",
+                date.format("%A, %b %-d"),
+                format_items(&items)
+            );
+            Ok(())
+        }
+        Command::Week { date, json, png } => {
+            let date = resolve_date(&date);
+            let days = flikisdining::fetch_week_lunch(date)
+                .await
+                .map_err(|why| why.to_string())?;
+
+            if png {
+                let path = format!("lunch-week-{}.png", date.format("%Y-%m-%d"));
+                std::fs::write(&path, render::render_week(&days)?)
+                    .map_err(|why| why.to_string())?;
+                println!("Saved {}", path);
+                return Ok(());
+            }
+
+            if json {
+                return print_json(&days);
+            }
+
+            let week = days
+                .iter()
+                .map(format_day)
+                .collect::<Vec<String>>()
+                .join("\n\n");
+            println!("{}", week);
+            Ok(())
+        }
+        Command::Search { query, json } => {
+            let results = search::search(&config::get().school, &query)
+                .await
+                .map_err(|why| why.to_string())?;
+
+            if json {
+                return print_json(&results);
+            }
+
+            for warning in &results.warnings {
+                eprintln!("warning: {}", warning);
+            }
+
+            if let Some(prediction) = &results.prediction {
+                println!(
+                    "Not on any published menu. Prediction: {} around {} ({}, {} confidence)",
+                    prediction.name,
+                    prediction.date.format("%A, %b %-d"),
+                    prediction.pattern,
+                    prediction.confidence_label()
+                );
+            } else if results.results.is_empty() {
+                println!("Nothing found");
+            }
+
+            for result in &results.results {
+                println!(
+                    "{}  {} {} (score {:.2})",
+                    result.date,
+                    result.name,
+                    result
+                        .tags
+                        .iter()
+                        .map(|tag| tag.emoji())
+                        .collect::<String>(),
+                    result.score
+                );
+            }
+
+            Ok(())
+        }
+        Command::Serve => {
+            config::watch();
+            metrics::start();
+            warm::start();
+
+            let addr = config::get()
+                .http_bind
+                .unwrap_or_else(|| DEFAULT_BIND.parse::<SocketAddr>().unwrap());
+            let served = api::serve(addr).await.map_err(|why| why.to_string());
+
+            // serving can fill in the menu history, save it before exiting
+            store::flush();
+            served
+        }
+    }
+}
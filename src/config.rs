@@ -0,0 +1,1193 @@
+use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
+use chrono_tz::Tz;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serenity::model::prelude::ChannelId;
+use std::{
+    collections::BTreeMap,
+    env,
+    fmt::Display,
+    net::SocketAddr,
+    path::Path,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, RwLock,
+    },
+    time::{Duration, SystemTime},
+};
+use thiserror::Error;
+use tracing::{error, info, warn};
+use tracing_subscriber::EnvFilter;
+
+/// where the config is read from if `CONFIG_FILE` isn't set
+const DEFAULT_PATH: &str = "config.toml";
+
+/// how often the config file is checked for changes
+const POLL_SECONDS: u64 = 5;
+
+/// settings that are only read once at startup, so changing them needs a restart
+const RESTART_ONLY: [&str; 6] = [
+    "token",
+    "data_dir",
+    "http_bind",
+    "metrics_bind",
+    "cache.",
+    "logging.",
+];
+
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("Failed to read {path}: {source}")]
+    ReadFailed {
+        path: String,
+        source: std::io::Error,
+    },
+
+    #[error("Failed to parse {path}: {source}")]
+    ParseFailed {
+        path: String,
+        source: toml::de::Error,
+    },
+
+    #[error("`{name}` must be set, in the config file or with the {env} environment variable")]
+    Missing {
+        name: &'static str,
+        env: &'static str,
+    },
+
+    #[error("Invalid `{name}` \"{value}\": {reason}")]
+    Invalid {
+        name: &'static str,
+        value: String,
+        reason: String,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct SchoolConfig {
+    /// the subdomain, `{key}.api.flikisdining.com`
+    pub key: String,
+    /// the school's slug in menu URLs
+    pub slug: String,
+    /// which menu to read, e.g. `lunch`
+    pub menu_type: String,
+}
+
+impl Default for SchoolConfig {
+    fn default() -> Self {
+        SchoolConfig {
+            key: String::new(),
+            slug: "kentucky-country-day-school".to_owned(),
+            menu_type: "lunch".to_owned(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct PermissionsConfig {
+    /// discord role ids that are bot admins in every server, on top of the
+    /// server admins and the roles granted with `/permissions`
+    pub admin_roles: Vec<u64>,
+    /// discord role ids that are bot moderators in every server
+    pub moderator_roles: Vec<u64>,
+    /// the level each command needs, for commands that shouldn't use their default
+    pub commands: BTreeMap<Command, Level>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct ChannelsConfig {
+    /// channels where every command works, not just "what lunch"
+    pub primary: Vec<u64>,
+}
+
+/// what a trigger rule runs
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum Command {
+    Lunch,
+    Search,
+    Ingredients,
+    Diet,
+    Watch,
+    Ratings,
+    Stats,
+    Calendar,
+    Week,
+    School,
+}
+
+impl Command {
+    /// the name used in the config, and as the metrics label
+    pub fn name(self) -> &'static str {
+        match self {
+            Command::Lunch => "lunch",
+            Command::Search => "search",
+            Command::Ingredients => "ingredients",
+            Command::Diet => "diet",
+            Command::Watch => "watch",
+            Command::Ratings => "ratings",
+            Command::Stats => "stats",
+            Command::Calendar => "calendar",
+            Command::Week => "week",
+            Command::School => "school",
+        }
+    }
+
+    /// The level needed to run the command, unless `[permissions.commands]` says otherwise.
+    pub fn level(self) -> Level {
+        match self {
+            Command::Lunch
+            | Command::Search
+            | Command::Ingredients
+            | Command::Diet
+            | Command::Watch
+            | Command::Ratings
+            | Command::Stats
+            | Command::Calendar
+            | Command::Week
+            | Command::School => Level::Everyone,
+        }
+    }
+}
+
+/// who can run a command, each level can do everything the ones before it can
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum Level {
+    #[default]
+    Everyone,
+    Moderator,
+    Admin,
+}
+
+/// where a trigger rule applies
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Scope {
+    /// in the primary channels, and in DMs and mentions since those are
+    /// always meant for the bot
+    #[default]
+    Primary,
+    /// anywhere the bot can read
+    Any,
+    /// only in direct messages
+    Dm,
+    /// any message that mentions the bot
+    Mention,
+}
+
+/// where a message came from, to check against a rule's [`Scope`]
+pub struct Origin {
+    pub primary: bool,
+    pub dm: bool,
+    pub mention: bool,
+}
+
+impl Scope {
+    fn includes(self, origin: &Origin) -> bool {
+        match self {
+            Scope::Primary => origin.primary || origin.dm || origin.mention,
+            Scope::Any => true,
+            Scope::Dm => origin.dm,
+            Scope::Mention => origin.mention,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct TriggerRule {
+    pub command: Command,
+    #[serde(default)]
+    pub scope: Scope,
+    /// words or phrases that all have to be in the message, as whole words
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub keywords: Vec<String>,
+    /// a regex the (lowercased) message has to match, instead of keywords
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub regex: Option<String>,
+
+    /// compiled from `keywords` or `regex` when the config is validated
+    #[serde(skip)]
+    patterns: Vec<Regex>,
+}
+
+impl TriggerRule {
+    fn new(command: Command, scope: Scope, keywords: &[&str], regex: Option<&str>) -> Self {
+        TriggerRule {
+            command,
+            scope,
+            keywords: keywords.iter().map(|keyword| keyword.to_string()).collect(),
+            regex: regex.map(str::to_owned),
+            patterns: Vec::new(),
+        }
+    }
+
+    /// Compiles the patterns, making sure there's exactly one kind.
+    fn compile(&mut self, number: usize) -> Result<(), ConfigError> {
+        let patterns = match (&self.regex, self.keywords.is_empty()) {
+            (Some(regex), true) => vec![regex.clone()],
+            (None, false) => self
+                .keywords
+                .iter()
+                .map(|keyword| keyword.trim().to_lowercase())
+                .filter(|keyword| !keyword.is_empty())
+                .map(|keyword| format!(r"\b{}\b", regex::escape(&keyword)))
+                .collect(),
+            _ => {
+                return Err(invalid(
+                    "triggers",
+                    format!("rule {}", number),
+                    "every rule needs either `keywords` or `regex`, not both",
+                ))
+            }
+        };
+
+        // an empty pattern would match every message
+        if patterns.iter().all(|pattern| pattern.trim().is_empty()) {
+            return Err(invalid(
+                "triggers",
+                format!("rule {}", number),
+                "the rule has an empty pattern",
+            ));
+        }
+
+        self.patterns = patterns
+            .iter()
+            .map(|pattern| {
+                Regex::new(pattern).map_err(|why| {
+                    invalid(
+                        "triggers",
+                        format!("rule {}", number),
+                        &format!("bad regex `{}`: {}", pattern, why),
+                    )
+                })
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(())
+    }
+
+    pub fn is_match(&self, content: &str) -> bool {
+        !self.patterns.is_empty()
+            && self
+                .patterns
+                .iter()
+                .all(|pattern| pattern.is_match(content))
+    }
+
+    /// The text after the (last) pattern, for commands that take an argument,
+    /// or what a regex captured as `(?P<arg>...)` if it has that group.
+    pub fn argument(&self, content: &str) -> Option<String> {
+        let captures = self.patterns.last()?.captures(content)?;
+        let argument = match captures.name("arg") {
+            Some(argument) => argument.as_str(),
+            None => &content[captures.get(0)?.end()..],
+        };
+
+        Some(argument.trim().to_owned())
+    }
+}
+
+/// The rules that reproduce the bot's original triggers, on whole words.
+fn default_triggers() -> Vec<TriggerRule> {
+    vec![
+        // these two come first, since they also mention lunch
+        TriggerRule::new(Command::Calendar, Scope::Any, &["lunch calendar"], None),
+        TriggerRule::new(Command::Week, Scope::Any, &["lunch week"], None),
+        TriggerRule::new(Command::Lunch, Scope::Primary, &["what", "lunch"], None),
+        TriggerRule::new(
+            Command::Lunch,
+            Scope::Any,
+            &[],
+            Some(r"\bwhat(?:['’]?s| is)?(?: for)? lunch\b"),
+        ),
+        TriggerRule::new(
+            Command::Search,
+            Scope::Primary,
+            &[],
+            Some(r"^when will we have\b"),
+        ),
+        TriggerRule::new(
+            Command::Ingredients,
+            Scope::Primary,
+            &[],
+            Some(r"^ingredients\b"),
+        ),
+        TriggerRule::new(Command::Diet, Scope::Primary, &[], Some(r"^diet\b")),
+        TriggerRule::new(
+            Command::Watch,
+            Scope::Primary,
+            &[],
+            Some(r"^(?P<arg>(?:watch|unwatch|watchlist)\b.*)"),
+        ),
+        TriggerRule::new(
+            Command::Ratings,
+            Scope::Primary,
+            &[],
+            Some(r"^(?P<arg>top|worst) lunches\b"),
+        ),
+        TriggerRule::new(Command::Stats, Scope::Primary, &[], Some(r"^stats\b")),
+        TriggerRule::new(Command::School, Scope::Primary, &[], Some(r"^school\b")),
+    ]
+}
+
+/// same as `http_cache::CacheMode`, so it can be read from the config
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum CacheMode {
+    #[default]
+    Default,
+    NoStore,
+    Reload,
+    NoCache,
+    ForceCache,
+    OnlyIfCached,
+    IgnoreRules,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct CacheConfig {
+    /// whether FlikIsDining responses are cached in memory at all
+    pub enabled: bool,
+    /// how the HTTP cache treats FlikIsDining's cache headers
+    pub mode: CacheMode,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        CacheConfig {
+            enabled: true,
+            mode: CacheMode::Default,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum LogFormat {
+    /// one line per event
+    #[default]
+    Text,
+    /// several lines per event, easier to read in a terminal
+    Pretty,
+    /// one JSON object per line, for log collectors
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text.trim().to_lowercase().as_str() {
+            "text" => Ok(LogFormat::Text),
+            "pretty" => Ok(LogFormat::Pretty),
+            "json" => Ok(LogFormat::Json),
+            _ => Err("expected `text`, `pretty` or `json`".to_owned()),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoggingConfig {
+    pub format: LogFormat,
+    /// which events to log, as a `tracing` filter (e.g. `info` or `info,lunchbotv2=debug`)
+    pub level: String,
+    /// response bodies longer than this are cut short in the log
+    pub max_body_bytes: usize,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        LoggingConfig {
+            format: LogFormat::Text,
+            level: "info,serenity=warn,tantivy=warn".to_owned(),
+            max_body_bytes: 2048,
+        }
+    }
+}
+
+/// a token bucket, `burst` requests at once and `per_minute` after that
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct BucketConfig {
+    pub burst: u32,
+    pub per_minute: f64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitConfig {
+    pub enabled: bool,
+    /// every command from one user
+    pub user: BucketConfig,
+    /// every command in one channel (or DM)
+    pub channel: BucketConfig,
+    /// searches from one user, on top of `user`, since each builds an index
+    pub search: BucketConfig,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        RateLimitConfig {
+            enabled: true,
+            user: BucketConfig {
+                burst: 5,
+                per_minute: 6.0,
+            },
+            channel: BucketConfig {
+                burst: 20,
+                per_minute: 30.0,
+            },
+            search: BucketConfig {
+                burst: 2,
+                per_minute: 2.0,
+            },
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct FeaturesConfig {
+    pub search: bool,
+    pub ingredients: bool,
+    pub diet: bool,
+    pub watch: bool,
+    pub ratings: bool,
+    pub stats: bool,
+    pub calendar: bool,
+    pub week_image: bool,
+    /// fetch past weeks into the menu history on startup
+    pub history_backfill: bool,
+    /// refresh this week's and next week's menus at `warm_at` on school days
+    pub cache_warmer: bool,
+}
+
+impl FeaturesConfig {
+    /// whether a command is turned on (asking for lunch always is)
+    pub fn enabled(&self, command: Command) -> bool {
+        match command {
+            Command::Lunch | Command::School => true,
+            Command::Search => self.search,
+            Command::Ingredients => self.ingredients,
+            Command::Diet => self.diet,
+            Command::Watch => self.watch,
+            Command::Ratings => self.ratings,
+            Command::Stats => self.stats,
+            Command::Calendar => self.calendar,
+            Command::Week => self.week_image,
+        }
+    }
+}
+
+impl Default for FeaturesConfig {
+    fn default() -> Self {
+        FeaturesConfig {
+            search: true,
+            ingredients: true,
+            diet: true,
+            watch: true,
+            ratings: true,
+            stats: true,
+            calendar: true,
+            week_image: true,
+            history_backfill: true,
+            cache_warmer: true,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// the discord bot token, only needed to run the bot
+    pub token: Option<String>,
+    /// the school's timezone, used to figure out what "today" is
+    pub timezone: Tz,
+    /// where the bot keeps its saved data (profiles, ratings, history, ...)
+    pub data_dir: String,
+    /// address for the JSON API (e.g. `127.0.0.1:8080`), the API is off when unset
+    pub http_bind: Option<SocketAddr>,
+    /// address for the Prometheus metrics (e.g. `127.0.0.1:9100`), off when unset
+    pub metrics_bind: Option<SocketAddr>,
+    /// how often the watchlist checks for newly published menus
+    pub watch_interval_minutes: u64,
+    /// how many past weeks to fetch into the menu history on startup
+    pub history_backfill_weeks: i64,
+    /// how many weeks ahead the calendar feed covers
+    pub calendar_weeks: i64,
+    /// how long after answering a message the bot still follows its edits and deletes
+    pub reply_window_minutes: u64,
+    /// when to refresh the menus on school days, shortly before lunch (school time)
+    pub warm_at: NaiveTime,
+    /// show the raw error under friendly error messages, for admins debugging
+    pub debug_errors: bool,
+    /// words to highlight in ingredient lists (e.g. `peanut`, `milk`)
+    pub ingredient_watch_terms: Vec<String>,
+
+    /// the default school
+    pub school: SchoolConfig,
+    /// other schools users can pick for themselves with `school <name>`
+    pub schools: BTreeMap<String, SchoolConfig>,
+    pub channels: ChannelsConfig,
+    pub permissions: PermissionsConfig,
+    /// checked in order, the first rule that matches a message runs its command
+    pub triggers: Vec<TriggerRule>,
+    pub cache: CacheConfig,
+    pub logging: LoggingConfig,
+    pub rate_limit: RateLimitConfig,
+    pub features: FeaturesConfig,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            token: None,
+            timezone: chrono_tz::America::New_York,
+            data_dir: "data".to_owned(),
+            http_bind: None,
+            metrics_bind: None,
+            watch_interval_minutes: 60,
+            history_backfill_weeks: 12,
+            calendar_weeks: 3,
+            reply_window_minutes: 10,
+            warm_at: NaiveTime::from_hms_opt(11, 30, 0).unwrap(),
+            debug_errors: false,
+            ingredient_watch_terms: Vec::new(),
+            school: SchoolConfig::default(),
+            schools: BTreeMap::new(),
+            channels: ChannelsConfig::default(),
+            permissions: PermissionsConfig::default(),
+            triggers: default_triggers(),
+            cache: CacheConfig::default(),
+            logging: LoggingConfig::default(),
+            rate_limit: RateLimitConfig::default(),
+            features: FeaturesConfig::default(),
+        }
+    }
+}
+
+impl Config {
+    /// whether every command works in this channel
+    pub fn is_primary(&self, channel: ChannelId) -> bool {
+        self.channels.primary.contains(&channel.get())
+    }
+
+    /// The first enabled trigger rule that matches a (lowercased) message.
+    pub fn trigger(&self, content: &str, origin: &Origin) -> Option<&TriggerRule> {
+        self.triggers.iter().find(|rule| {
+            self.features.enabled(rule.command)
+                && rule.scope.includes(origin)
+                && rule.is_match(content)
+        })
+    }
+
+    /// The argument after a command's trigger, e.g. the search term after
+    /// "when will we have". Empty when nothing comes after it.
+    pub fn argument(&self, command: Command, content: &str) -> Option<String> {
+        self.triggers
+            .iter()
+            .filter(|rule| rule.command == command && rule.is_match(content))
+            .find_map(|rule| rule.argument(content))
+    }
+
+    /// today's date at the school
+    pub fn today(&self) -> NaiveDate {
+        Utc::now().with_timezone(&self.timezone).date_naive()
+    }
+
+    /// The level needed to run a command.
+    pub fn required_level(&self, command: Command) -> Level {
+        self.permissions
+            .commands
+            .get(&command)
+            .copied()
+            .unwrap_or(command.level())
+    }
+
+    /// The whole config as TOML, with the token left out, for `/admin diag`.
+    pub fn describe(&self) -> Result<String, toml::ser::Error> {
+        let mut config = self.clone();
+        if config.token.is_some() {
+            config.token = Some("(redacted)".to_owned());
+        }
+
+        toml::to_string_pretty(&config)
+    }
+
+    /// midday at the school on a date, which is always safely inside that day
+    pub fn midday(&self, date: NaiveDate) -> DateTime<Utc> {
+        date.and_hms_opt(12, 0, 0)
+            .and_then(|date| date.and_local_timezone(self.timezone).single())
+            .map(|date| date.with_timezone(&Utc))
+            .unwrap_or_else(|| date.and_hms_opt(12, 0, 0).unwrap_or_default().and_utc())
+    }
+
+    /// A `yyyy-mm-dd` date at midday at the school, like the bot's own dates.
+    pub fn parse_date(&self, date: &str) -> Option<DateTime<Utc>> {
+        NaiveDate::parse_from_str(date, "%Y-%m-%d")
+            .ok()
+            .map(|date| self.midday(date))
+    }
+
+    /// Applies the environment variables, which win over the config file.
+    fn apply_env(&mut self) -> Result<(), ConfigError> {
+        if let Ok(token) = env::var("TOKEN") {
+            self.token = Some(token);
+        }
+        if let Ok(key) = env::var("API_SCHOOL_KEY") {
+            self.school.key = key;
+        }
+        if let Ok(slug) = env::var("SCHOOL_SLUG") {
+            self.school.slug = slug;
+        }
+        if let Ok(menu_type) = env::var("MENU_TYPE") {
+            self.school.menu_type = menu_type;
+        }
+        if let Ok(data_dir) = env::var("DATA_DIR") {
+            self.data_dir = data_dir;
+        }
+        if let Ok(channels) = env::var("PRIMARY_LUNCH_CHANNEL") {
+            self.channels.primary = split_list(&channels)
+                .iter()
+                .map(|channel| parse("channels.primary", channel))
+                .collect::<Result<_, _>>()?;
+        }
+        if let Ok(roles) = env::var("ADMIN_ROLES") {
+            self.permissions.admin_roles = split_list(&roles)
+                .iter()
+                .map(|role| parse("permissions.admin_roles", role))
+                .collect::<Result<_, _>>()?;
+        }
+        if let Ok(roles) = env::var("MODERATOR_ROLES") {
+            self.permissions.moderator_roles = split_list(&roles)
+                .iter()
+                .map(|role| parse("permissions.moderator_roles", role))
+                .collect::<Result<_, _>>()?;
+        }
+        if let Ok(terms) = env::var("INGREDIENT_WATCH_TERMS") {
+            self.ingredient_watch_terms = split_list(&terms);
+        }
+
+        override_with(&mut self.timezone, "timezone", "TIMEZONE")?;
+        override_with(
+            &mut self.watch_interval_minutes,
+            "watch_interval_minutes",
+            "WATCH_INTERVAL_MINUTES",
+        )?;
+        override_with(
+            &mut self.history_backfill_weeks,
+            "history_backfill_weeks",
+            "HISTORY_BACKFILL_WEEKS",
+        )?;
+        override_with(&mut self.calendar_weeks, "calendar_weeks", "CALENDAR_WEEKS")?;
+        override_with(
+            &mut self.reply_window_minutes,
+            "reply_window_minutes",
+            "REPLY_WINDOW_MINUTES",
+        )?;
+        override_with(&mut self.warm_at, "warm_at", "WARM_AT")?;
+        override_with(&mut self.debug_errors, "debug_errors", "DEBUG_ERRORS")?;
+        override_with(&mut self.logging.format, "logging.format", "LOG_FORMAT")?;
+        if let Ok(level) = env::var("LOG_LEVEL") {
+            self.logging.level = level;
+        }
+
+        if let Ok(addr) = env::var("HTTP_BIND") {
+            self.http_bind = Some(parse("http_bind", &addr)?);
+        }
+        if let Ok(addr) = env::var("METRICS_BIND") {
+            self.metrics_bind = Some(parse("metrics_bind", &addr)?);
+        }
+
+        Ok(())
+    }
+
+    /// Checks everything that would otherwise only fail once it's used.
+    fn validate(&mut self) -> Result<(), ConfigError> {
+        if self.school.key.trim().is_empty() {
+            return Err(ConfigError::Missing {
+                name: "school.key",
+                env: "API_SCHOOL_KEY",
+            });
+        }
+
+        // users type school names lowercased
+        self.schools = std::mem::take(&mut self.schools)
+            .into_iter()
+            .map(|(name, school)| (name.to_lowercase(), school))
+            .collect();
+
+        for (name, school) in &self.schools {
+            if school.key.trim().is_empty() {
+                return Err(invalid("schools", name, "the school needs a `key`"));
+            }
+        }
+
+        for (name, value) in [&self.school]
+            .into_iter()
+            .chain(self.schools.values())
+            .flat_map(|school| {
+                [
+                    ("school.key", &school.key),
+                    ("school.slug", &school.slug),
+                    ("school.menu_type", &school.menu_type),
+                ]
+            })
+            .chain(self.schools.keys().map(|name| ("schools", name)))
+        {
+            if value.is_empty()
+                || !value
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+            {
+                return Err(invalid(
+                    name,
+                    value,
+                    "only letters, numbers, `-` and `_` are allowed",
+                ));
+            }
+        }
+
+        if self
+            .token
+            .as_deref()
+            .is_some_and(|token| token.trim().is_empty())
+        {
+            self.token = None;
+        }
+
+        if self.watch_interval_minutes == 0 {
+            return Err(invalid("watch_interval_minutes", 0, "must be at least 1"));
+        }
+        if self.history_backfill_weeks < 0 {
+            return Err(invalid(
+                "history_backfill_weeks",
+                self.history_backfill_weeks,
+                "can't be negative",
+            ));
+        }
+        if self.calendar_weeks < 1 {
+            return Err(invalid(
+                "calendar_weeks",
+                self.calendar_weeks,
+                "must be at least 1",
+            ));
+        }
+        if self.reply_window_minutes == 0 {
+            return Err(invalid("reply_window_minutes", 0, "must be at least 1"));
+        }
+
+        for (name, limit) in [
+            ("rate_limit.user", &self.rate_limit.user),
+            ("rate_limit.channel", &self.rate_limit.channel),
+            ("rate_limit.search", &self.rate_limit.search),
+        ] {
+            if limit.burst == 0 {
+                return Err(invalid(name, 0, "`burst` must be at least 1"));
+            }
+            if limit.per_minute.is_nan() || limit.per_minute <= 0.0 {
+                return Err(invalid(
+                    name,
+                    limit.per_minute,
+                    "`per_minute` must be more than 0",
+                ));
+            }
+        }
+
+        if let Err(why) = EnvFilter::try_new(&self.logging.level) {
+            return Err(invalid(
+                "logging.level",
+                &self.logging.level,
+                &why.to_string(),
+            ));
+        }
+
+        for (i, rule) in self.triggers.iter_mut().enumerate() {
+            rule.compile(i + 1)?;
+        }
+
+        self.ingredient_watch_terms = self
+            .ingredient_watch_terms
+            .iter()
+            .map(|term| term.trim().to_lowercase())
+            .filter(|term| !term.is_empty())
+            .collect();
+
+        Ok(())
+    }
+
+    /// Reads the config file (`CONFIG_FILE`, or `config.toml` if it exists),
+    /// applies the environment on top and validates the result.
+    pub fn load() -> Result<Config, ConfigError> {
+        let (path, required) = path();
+
+        let mut config = if required || Path::new(&path).exists() {
+            let text =
+                std::fs::read_to_string(&path).map_err(|source| ConfigError::ReadFailed {
+                    path: path.clone(),
+                    source,
+                })?;
+
+            toml::from_str(&text).map_err(|source| ConfigError::ParseFailed {
+                path: path.clone(),
+                source,
+            })?
+        } else {
+            Config::default()
+        };
+
+        config.apply_env()?;
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+/// the current config, swapped out whole on every reload
+static CONFIG: RwLock<Option<Arc<Config>>> = RwLock::new(None);
+
+/// so reloads from the file watcher and SIGHUP don't interleave
+static RELOADING: Mutex<()> = Mutex::new(());
+
+/// so reconnects don't start a second watcher
+static WATCHING: AtomicBool = AtomicBool::new(false);
+
+/// Loads the config for the rest of the process. Call once at startup.
+pub fn init() -> Result<(), ConfigError> {
+    let config = Config::load()?;
+    *CONFIG.write().unwrap() = Some(Arc::new(config));
+    Ok(())
+}
+
+/// The current config. Hold on to it for the length of one request so
+/// everything sees the same settings, even if a reload happens meanwhile.
+pub fn get() -> Arc<Config> {
+    CONFIG
+        .read()
+        .unwrap()
+        .clone()
+        .expect("config::init should be called at startup")
+}
+
+/// Every setting as `dotted.key = value`, for comparing configs.
+fn flatten(prefix: String, value: &serde_json::Value, settings: &mut BTreeMap<String, String>) {
+    match value {
+        serde_json::Value::Object(map) => {
+            for (key, value) in map {
+                let key = if prefix.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", prefix, key)
+                };
+                flatten(key, value, settings);
+            }
+        }
+        value => {
+            settings.insert(prefix, value.to_string());
+        }
+    }
+}
+
+/// Describes every setting that differs between two configs, including ones
+/// that were added or removed.
+fn changes(old: &Config, new: &Config) -> Vec<String> {
+    let mut before = BTreeMap::new();
+    let mut after = BTreeMap::new();
+    flatten(String::new(), &serde_json::json!(old), &mut before);
+    flatten(String::new(), &serde_json::json!(new), &mut after);
+
+    let keys = before
+        .keys()
+        .chain(after.keys())
+        .collect::<std::collections::BTreeSet<&String>>();
+
+    keys.into_iter()
+        .filter(|key| before.get(*key) != after.get(*key))
+        .map(|key| {
+            let restart = if RESTART_ONLY.iter().any(|prefix| key.starts_with(prefix)) {
+                " (takes effect after a restart)"
+            } else {
+                ""
+            };
+
+            // don't put the token in the logs
+            if key == "token" {
+                format!("token was changed{}", restart)
+            } else {
+                format!(
+                    "{}: {} -> {}{}",
+                    key,
+                    before.get(key).map(String::as_str).unwrap_or("(unset)"),
+                    after.get(key).map(String::as_str).unwrap_or("(unset)"),
+                    restart
+                )
+            }
+        })
+        .collect()
+}
+
+/// Loads the config again and swaps it in if it's valid, logging what
+/// changed. An invalid config is logged and the current one is kept.
+pub fn reload() {
+    let _reloading = RELOADING.lock().unwrap();
+
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(why) => {
+            warn!("Not reloading config, keeping the current one: {}", why);
+            return;
+        }
+    };
+
+    // always swap it in, the list of changes is only for the logs
+    let changes = changes(&get(), &config);
+    *CONFIG.write().unwrap() = Some(Arc::new(config));
+
+    if changes.is_empty() {
+        info!("Config reloaded, nothing changed");
+        return;
+    }
+
+    info!("Config reloaded");
+    for change in changes {
+        info!("  {}", change);
+    }
+}
+
+fn modified(path: &str) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+/// Reloads the config whenever the file changes or the process gets a
+/// SIGHUP. Does nothing if it's already watching.
+pub fn watch() {
+    if WATCHING.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    let (path, _) = path();
+
+    tokio::spawn(async move {
+        let mut last_modified = modified(&path);
+        let mut interval = tokio::time::interval(Duration::from_secs(POLL_SECONDS));
+
+        loop {
+            interval.tick().await;
+
+            let modified = modified(&path);
+            if modified == last_modified {
+                continue;
+            }
+
+            last_modified = modified;
+            info!("{} changed, reloading config", path);
+            tokio::task::spawn_blocking(reload);
+        }
+    });
+
+    #[cfg(unix)]
+    tokio::spawn(async move {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut hangup = match signal(SignalKind::hangup()) {
+            Ok(hangup) => hangup,
+            Err(why) => {
+                error!("Failed to listen for SIGHUP: {:?}", why);
+                return;
+            }
+        };
+
+        while hangup.recv().await.is_some() {
+            info!("Got SIGHUP, reloading config");
+            tokio::task::spawn_blocking(reload);
+        }
+    });
+}
+
+/// The config file path, and whether it has to exist (it does if `CONFIG_FILE` was set).
+fn path() -> (String, bool) {
+    match env::var("CONFIG_FILE") {
+        Ok(path) => (path, true),
+        Err(_) => (DEFAULT_PATH.to_owned(), false),
+    }
+}
+
+fn split_list(list: &str) -> Vec<String> {
+    list.split(',')
+        .map(|item| item.trim().to_owned())
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+fn invalid(name: &'static str, value: impl Display, reason: &str) -> ConfigError {
+    ConfigError::Invalid {
+        name,
+        value: value.to_string(),
+        reason: reason.to_owned(),
+    }
+}
+
+fn parse<T: FromStr>(name: &'static str, value: &str) -> Result<T, ConfigError>
+where
+    T::Err: Display,
+{
+    value
+        .trim()
+        .parse()
+        .map_err(|why| invalid(name, value, &format!("{}", why)))
+}
+
+/// Replaces `value` with the environment variable `var`, if it's set.
+fn override_with<T: FromStr>(
+    value: &mut T,
+    name: &'static str,
+    var: &str,
+) -> Result<(), ConfigError>
+where
+    T::Err: Display,
+{
+    if let Ok(text) = env::var(var) {
+        *value = parse(name, &text)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{changes, Command, Config, Origin};
+
+    /// A validated config with the given trigger rules (and the default school key).
+    fn with_triggers(triggers: &str) -> Config {
+        let mut config: Config =
+            toml::from_str(&format!("[school]\nkey = \"test\"\n\n{}", triggers)).unwrap();
+        config.validate().unwrap();
+        config
+    }
+
+    const PRIMARY: Origin = Origin {
+        primary: true,
+        dm: false,
+        mention: false,
+    };
+
+    #[test]
+    fn custom_keyword_rule_takes_the_words_after_it() {
+        let config = with_triggers("[[triggers]]\ncommand = \"watch\"\nkeywords = [\"follow\"]");
+
+        let rule = config.trigger("follow pizza", &PRIMARY).unwrap();
+        assert_eq!(rule.command, Command::Watch);
+        assert_eq!(
+            config.argument(Command::Watch, "follow pizza").as_deref(),
+            Some("pizza")
+        );
+
+        // whole words only
+        assert!(config.trigger("followers pizza", &PRIMARY).is_none());
+    }
+
+    #[test]
+    fn custom_regex_rule_takes_the_text_after_it() {
+        let config =
+            with_triggers("[[triggers]]\ncommand = \"stats\"\nregex = '^how often do we have\\b'");
+
+        let rule = config
+            .trigger("how often do we have tacos", &PRIMARY)
+            .unwrap();
+        assert_eq!(rule.command, Command::Stats);
+        assert_eq!(
+            config
+                .argument(Command::Stats, "how often do we have tacos")
+                .as_deref(),
+            Some("tacos")
+        );
+    }
+
+    #[test]
+    fn regex_rule_can_capture_the_argument() {
+        let config = with_triggers(
+            "[[triggers]]\ncommand = \"ingredients\"\nregex = '^what is in (?P<arg>.+?)\\??$'",
+        );
+
+        assert_eq!(
+            config
+                .argument(Command::Ingredients, "what is in the chili tomorrow?")
+                .as_deref(),
+            Some("the chili tomorrow")
+        );
+    }
+
+    #[test]
+    fn default_rules_keep_the_verb() {
+        let config = with_triggers("");
+
+        assert_eq!(
+            config.argument(Command::Watch, "unwatch pizza").as_deref(),
+            Some("unwatch pizza")
+        );
+        assert_eq!(
+            config
+                .argument(Command::Ratings, "worst lunches")
+                .as_deref(),
+            Some("worst")
+        );
+        assert_eq!(
+            config
+                .argument(Command::Diet, "diet avoid peanuts")
+                .as_deref(),
+            Some("avoid peanuts")
+        );
+        assert_eq!(
+            config
+                .argument(Command::Search, "when will we have pizza")
+                .as_deref(),
+            Some("pizza")
+        );
+    }
+
+    #[test]
+    fn rules_need_exactly_one_pattern_kind() {
+        for triggers in [
+            "[[triggers]]\ncommand = \"stats\"",
+            "[[triggers]]\ncommand = \"stats\"\nkeywords = [\"stats\"]\nregex = 'stats'",
+            "[[triggers]]\ncommand = \"stats\"\nkeywords = [\" \"]",
+            "[[triggers]]\ncommand = \"stats\"\nregex = '(unclosed'",
+        ] {
+            let mut config: Config =
+                toml::from_str(&format!("[school]\nkey = \"test\"\n\n{}", triggers)).unwrap();
+            assert!(config.validate().is_err(), "{}", triggers);
+        }
+    }
+
+    #[test]
+    fn changes_lists_added_changed_and_removed_settings() {
+        let old = with_triggers(
+            "[permissions.commands]\nstats = \"moderator\"\n\n[schools.north]\nkey = \"north\"",
+        );
+        let new = with_triggers("[permissions.commands]\nstats = \"admin\"");
+
+        let changed = changes(&old, &new);
+        assert!(
+            changed.contains(&"permissions.commands.stats: \"moderator\" -> \"admin\"".to_owned())
+        );
+        assert!(changed.contains(&"schools.north.key: \"north\" -> (unset)".to_owned()));
+
+        // removing the only entry is still a change
+        let changed = changes(&new, &with_triggers(""));
+        assert_eq!(
+            changed,
+            vec!["permissions.commands.stats: \"admin\" -> (unset)".to_owned()]
+        );
+
+        assert!(changes(&old, &old).is_empty());
+    }
+}
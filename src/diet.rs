@@ -0,0 +1,421 @@
+use crate::config::{self, Command};
+use crate::flikisdining::{FlikIsDiningFood, FlikIsDiningMenuItem, FoodTag};
+use crate::metrics;
+use crate::reply::{self, Reply};
+use crate::store::JsonStore;
+use chrono::{DateTime, Utc};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serenity::{
+    all::CreateEmbedFooter,
+    builder::{CreateEmbed, CreateMessage},
+    model::prelude::{Message, User, UserId},
+    prelude::Context,
+};
+use std::collections::HashMap;
+use tracing::error;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Diet {
+    Vegetarian,
+    Vegan,
+}
+
+#[derive(Serialize, Deserialize, Default, Clone, Debug)]
+pub struct DietaryProfile {
+    /// allergens to warn about
+    pub avoid: Vec<FoodTag>,
+    pub diet: Option<Diet>,
+}
+
+impl DietaryProfile {
+    fn is_empty(&self) -> bool {
+        self.avoid.is_empty() && self.diet.is_none()
+    }
+}
+
+/// saved profiles, keyed by discord user id
+static PROFILES: Lazy<JsonStore<HashMap<u64, DietaryProfile>>> =
+    Lazy::new(|| JsonStore::open("profiles"));
+
+/// ingredients that look like an allergen but aren't, e.g. peanut butter isn't milk
+static NOT_ALLERGENS: Lazy<HashMap<FoodTag, &str>> = Lazy::new(|| {
+    [(
+        FoodTag::Milk,
+        "peanut butter|cocoa butter|coconut milk|coconut cream",
+    )]
+    .into_iter()
+    .collect()
+});
+
+/// ingredient keywords for each allergen, for foods that are missing the icon.
+/// The keywords are the regex's first group, anything in [`NOT_ALLERGENS`]
+/// matches without it.
+static ALLERGEN_KEYWORDS: Lazy<HashMap<FoodTag, Regex>> = Lazy::new(|| {
+    [
+        (
+            FoodTag::Milk,
+            "milk|cheese|butter|cream|whey|casein|yogurt|lactose",
+        ),
+        (FoodTag::Egg, "eggs?|mayonnaise|albumin"),
+        (
+            FoodTag::Fish,
+            "fish|salmon|tuna|cod|tilapia|pollock|anchovy|anchovies",
+        ),
+        (
+            FoodTag::Shellfish,
+            "shellfish|shrimp|crab|lobster|clams?|oysters?|scallops?",
+        ),
+        (
+            FoodTag::TreeNuts,
+            "tree nuts?|almonds?|cashews?|walnuts?|pecans?|pistachios?|hazelnuts?|macadamia",
+        ),
+        (FoodTag::Peanuts, "peanuts?"),
+        (FoodTag::Wheat, "wheat|gluten|barley|rye|semolina"),
+        (FoodTag::Soy, "soy|soybeans?|tofu|edamame"),
+        (FoodTag::Sesame, "sesame|tahini"),
+        (FoodTag::Pork, "pork|bacon|ham|pepperoni|prosciutto"),
+    ]
+    .into_iter()
+    .map(|(tag, words)| {
+        let pattern = match NOT_ALLERGENS.get(&tag) {
+            Some(not) => format!(r"(?i)\b(?:{}|({}))\b", not, words),
+            None => format!(r"(?i)\b({})\b", words),
+        };
+
+        (tag, Regex::new(&pattern).unwrap())
+    })
+    .collect()
+});
+
+/// The first allergen keyword for the tag in an ingredient list.
+fn find_allergen(tag: FoodTag, ingredients: &str) -> Option<String> {
+    ALLERGEN_KEYWORDS
+        .get(&tag)?
+        .captures_iter(ingredients)
+        .find_map(|captures| captures.get(1))
+        .map(|found| found.as_str().to_lowercase())
+}
+
+/// The allergens named in a list like "peanuts, milk". A bare "nuts" could
+/// mean either kind, so it's both tree nuts and peanuts.
+fn parse_allergens(list: &str) -> Vec<FoodTag> {
+    let words = list
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<&str>>();
+
+    let mut allergens: Vec<FoodTag> = Vec::new();
+    for (i, word) in words.iter().enumerate() {
+        let tags = match word.trim_end_matches('s') {
+            "nut" if i == 0 || words[i - 1] != "tree" => vec![FoodTag::TreeNuts, FoodTag::Peanuts],
+            _ => FoodTag::from_word(word)
+                .filter(FoodTag::is_allergen)
+                .into_iter()
+                .collect(),
+        };
+
+        for tag in tags {
+            if !allergens.contains(&tag) {
+                allergens.push(tag);
+            }
+        }
+    }
+
+    allergens
+}
+
+/// ingredients that mean something isn't vegetarian
+static MEAT_KEYWORDS: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?i)\b(chicken|beef|pork|turkey|bacon|ham|sausage|pepperoni|meat|gelatin|anchovy|anchovies|fish|tuna|salmon|shrimp)\b")
+        .unwrap()
+});
+
+/// and the ones that additionally rule out vegan
+static ANIMAL_KEYWORDS: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?i)\b(honey)\b").unwrap());
+
+pub fn get_profile(user: UserId) -> Option<DietaryProfile> {
+    PROFILES.read(|profiles| profiles.get(&user.get()).cloned())
+}
+
+/// Returns why the food is unsafe for the profile, or nothing if it looks fine.
+fn check_food(profile: &DietaryProfile, food: &FlikIsDiningFood) -> Vec<String> {
+    let tags = food.tags();
+    let ingredients = food.ingredients.as_deref().unwrap_or("");
+    let mut reasons = Vec::new();
+
+    // does the food contain the allergen, either by icon or by ingredient list
+    let contains = |tag: FoodTag| -> Option<String> {
+        if tags.contains(&tag) {
+            return Some(format!("{} {}", tag.emoji(), tag.label()));
+        }
+
+        find_allergen(tag, ingredients)
+            .map(|found| format!("{} {} (ingredients: {})", tag.emoji(), tag.label(), found))
+    };
+
+    for tag in &profile.avoid {
+        // gluten free foods are fine for people avoiding wheat, even if an ingredient says otherwise
+        if *tag == FoodTag::Wheat && tags.contains(&FoodTag::GlutenFree) {
+            continue;
+        }
+
+        if let Some(reason) = contains(*tag) {
+            reasons.push(reason);
+        }
+    }
+
+    if let Some(diet) = profile.diet {
+        let tagged_vegan = tags.contains(&FoodTag::Vegan);
+        let tagged_vegetarian = tagged_vegan || tags.contains(&FoodTag::Vegetarian);
+
+        if !tagged_vegetarian {
+            let meat = [FoodTag::Fish, FoodTag::Shellfish, FoodTag::Pork]
+                .into_iter()
+                .find(|tag| tags.contains(tag))
+                .map(|tag| tag.label().to_lowercase())
+                .or_else(|| {
+                    MEAT_KEYWORDS
+                        .find(ingredients)
+                        .map(|found| found.as_str().to_lowercase())
+                });
+
+            if let Some(meat) = meat {
+                reasons.push(format!("🥩 Not vegetarian ({})", meat));
+            }
+        }
+
+        // checked on its own, so a dish with meat and cheese says both
+        if diet == Diet::Vegan && !tagged_vegan {
+            let animal = [FoodTag::Milk, FoodTag::Egg]
+                .into_iter()
+                .filter_map(contains)
+                .chain(
+                    ANIMAL_KEYWORDS
+                        .find(ingredients)
+                        .map(|found| found.as_str().to_lowercase()),
+                );
+
+            for animal in animal {
+                reasons.push(format!("🐄 Not vegan: {}", animal));
+            }
+        }
+    }
+
+    reasons
+}
+
+/// Formats a warning line for every item on the menu that's unsafe for the profile.
+pub fn check_menu(profile: &DietaryProfile, menu: &[FlikIsDiningMenuItem]) -> Vec<String> {
+    menu.iter()
+        .filter_map(|item| item.food.as_ref())
+        .filter_map(|food| {
+            let reasons = check_food(profile, food);
+
+            if reasons.is_empty() {
+                None
+            } else {
+                Some(format!("**{}**: {}", food.name, reasons.join(", ")))
+            }
+        })
+        .collect()
+}
+
+/// DMs the user about anything on the menu that doesn't fit their profile.
+pub async fn warn_user(
+    context: &Context,
+    user: &User,
+    menu: &[FlikIsDiningMenuItem],
+    date: DateTime<Utc>,
+) {
+    let Some(profile) = get_profile(user.id) else {
+        return;
+    };
+
+    let warnings = check_menu(&profile, menu);
+    if warnings.is_empty() {
+        return;
+    }
+
+    let embed = CreateEmbed::new()
+        .title(format!("⚠️ Heads up for {}", date.format("%A, %b %-d")))
+        .description(warnings.join("\n"))
+        .footer(CreateEmbedFooter::new(
+            "Based on your diet profile. Always double check with the cafeteria!",
+        ))
+        .color(0xE53935);
+
+    if let Err(why) = user
+        .direct_message(&context.http, CreateMessage::new().embed(embed))
+        .await
+    {
+        error!("Error sending diet warning: {:?}", why);
+        metrics::SEND_FAILURES.with_label_values(&["dm"]).inc();
+    }
+}
+
+fn describe(profile: &DietaryProfile) -> String {
+    if profile.is_empty() {
+        return "You don't have a diet profile. Set one with `diet avoid peanuts, milk` or `diet vegetarian`."
+            .to_owned();
+    }
+
+    let avoid = if profile.avoid.is_empty() {
+        "nothing".to_owned()
+    } else {
+        profile
+            .avoid
+            .iter()
+            .map(|tag| format!("{} {}", tag.emoji(), tag.label()))
+            .collect::<Vec<String>>()
+            .join(", ")
+    };
+
+    let diet = match profile.diet {
+        Some(Diet::Vegetarian) => "vegetarian",
+        Some(Diet::Vegan) => "vegan",
+        None => "none",
+    };
+
+    format!("**Avoiding:** {}\n**Diet:** {}", avoid, diet)
+}
+
+pub async fn handle(context: Context, msg: Message) {
+    // ignore bots
+    if msg.author.bot {
+        return;
+    }
+
+    // get the message content so we can match on it
+    let content = msg.content.to_lowercase();
+
+    // find the content after the diet trigger ("diet")
+    let args = config::get()
+        .argument(Command::Diet, &content)
+        .unwrap_or_default();
+
+    let (action, rest) = args.split_once(' ').unwrap_or((&args, ""));
+
+    // allergens mentioned in the rest of the message
+    let allergens = parse_allergens(rest);
+
+    let user = msg.author.id.get();
+    let reply = match action {
+        "" | "show" => describe(&get_profile(msg.author.id).unwrap_or_default()),
+        "avoid" | "allow" if allergens.is_empty() => {
+            "I didn't recognize any allergens. Try milk, egg, fish, shellfish, tree nuts, peanuts, wheat, soy, sesame or pork."
+                .to_owned()
+        }
+        "avoid" => PROFILES.update(|profiles| {
+            let profile = profiles.entry(user).or_default();
+            for tag in allergens {
+                if !profile.avoid.contains(&tag) {
+                    profile.avoid.push(tag);
+                }
+            }
+            describe(profile)
+        }),
+        "allow" => PROFILES.update(|profiles| {
+            let profile = profiles.entry(user).or_default();
+            profile.avoid.retain(|tag| !allergens.contains(tag));
+            describe(profile)
+        }),
+        "vegetarian" | "vegan" | "none" => PROFILES.update(|profiles| {
+            let profile = profiles.entry(user).or_default();
+            profile.diet = match action {
+                "vegetarian" => Some(Diet::Vegetarian),
+                "vegan" => Some(Diet::Vegan),
+                _ => None,
+            };
+            describe(profile)
+        }),
+        "clear" => {
+            PROFILES.update(|profiles| profiles.remove(&user));
+            "Cleared your diet profile.".to_owned()
+        }
+        _ => "Usage: `diet`, `diet avoid <allergens>`, `diet allow <allergens>`, `diet vegetarian|vegan|none` or `diet clear`"
+            .to_owned(),
+    };
+
+    let embed = CreateEmbed::new()
+        .title("🥗 Diet Profile")
+        .description(reply)
+        .color(0x43A047);
+
+    if let Err(why) = reply::send(&context, &msg, Reply::new().embed(embed)).await {
+        error!("Error sending message: {:?}", why);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_food, find_allergen, parse_allergens, Diet, DietaryProfile};
+    use crate::flikisdining::{FlikIsDiningFood, FoodTag};
+
+    fn food(ingredients: &str) -> FlikIsDiningFood {
+        FlikIsDiningFood {
+            id: 1.0,
+            name: "Pepperoni Pizza".to_owned(),
+            ingredients: Some(ingredients.to_owned()),
+            rounded_nutrition_info: None,
+            serving_size_info: None,
+            icons: None,
+        }
+    }
+
+    #[test]
+    fn vegan_reasons_are_listed_with_other_reasons() {
+        let profile = DietaryProfile {
+            avoid: Vec::new(),
+            diet: Some(Diet::Vegan),
+        };
+
+        let reasons = check_food(
+            &profile,
+            &food("dough, mozzarella (milk), pepperoni, honey"),
+        );
+
+        assert!(reasons
+            .iter()
+            .any(|reason| reason.contains("Not vegetarian")));
+        assert!(reasons
+            .iter()
+            .any(|reason| reason.contains("Not vegan") && reason.contains("Milk")));
+        assert!(reasons
+            .iter()
+            .any(|reason| reason.contains("Not vegan: honey")));
+    }
+
+    #[test]
+    fn nut_butters_arent_milk() {
+        assert_eq!(
+            find_allergen(FoodTag::Milk, "bread, peanut butter, jelly"),
+            None
+        );
+        assert_eq!(find_allergen(FoodTag::Milk, "Cocoa Butter, sugar"), None);
+        assert_eq!(
+            find_allergen(FoodTag::Milk, "peanut butter, butter"),
+            Some("butter".to_owned())
+        );
+        assert_eq!(
+            find_allergen(FoodTag::Peanuts, "bread, peanut butter"),
+            Some("peanut".to_owned())
+        );
+    }
+
+    #[test]
+    fn bare_nuts_means_both_kinds() {
+        assert_eq!(
+            parse_allergens("nuts"),
+            vec![FoodTag::TreeNuts, FoodTag::Peanuts]
+        );
+        assert_eq!(parse_allergens("tree nuts"), vec![FoodTag::TreeNuts]);
+        assert_eq!(
+            parse_allergens("peanuts, milk"),
+            vec![FoodTag::Peanuts, FoodTag::Milk]
+        );
+        assert_eq!(parse_allergens("eggplant"), vec![]);
+    }
+}
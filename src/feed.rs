@@ -0,0 +1,172 @@
+use crate::config;
+use crate::flikisdining::{self, FetchError, FlikIsDiningDay};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use tokio::task::JoinSet;
+use tracing::{error, warn};
+
+/// weeks to include, relative to this week (last week through next week)
+const FEED_WEEKS: std::ops::Range<i64> = -1..2;
+
+/// Escapes text for XML (and HTML, same rules).
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// Parses `last_updated`, which doesn't always come with a timezone.
+fn parse_updated(updated: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(updated)
+        .map(|date| date.with_timezone(&Utc))
+        .ok()
+        .or_else(|| {
+            NaiveDateTime::parse_from_str(updated, "%Y-%m-%dT%H:%M:%S%.f")
+                .ok()
+                .map(|date| date.and_utc())
+        })
+}
+
+/// Midnight at the school on a date, the `updated` time for days FlikIsDining
+/// doesn't give one for, so they don't show as changed on every poll.
+fn midnight(date: NaiveDate) -> DateTime<Utc> {
+    let timezone = config::get().timezone;
+
+    date.and_hms_opt(0, 0, 0)
+        .and_then(|date| date.and_local_timezone(timezone).earliest())
+        .map(|date| date.with_timezone(&Utc))
+        .unwrap_or_else(|| date.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc())
+}
+
+/// The menu for a day as an HTML list, with thumbnails where there are any.
+fn render_html(day: &FlikIsDiningDay) -> String {
+    let items = day
+        .menu_items
+        .iter()
+        .filter_map(|item| item.food.as_ref().map(|food| (item, food)))
+        .map(|(item, food)| {
+            let thumbnail = item
+                .image_thumbnail
+                .as_ref()
+                .map(|src| {
+                    format!(
+                        "<img src=\"{}\" alt=\"\" width=\"48\" height=\"48\" /> ",
+                        escape(src)
+                    )
+                })
+                .unwrap_or_default();
+
+            let calories = food
+                .rounded_nutrition_info
+                .as_ref()
+                .and_then(|info| info.calories)
+                .map(|calories| format!(" - {} cals", calories))
+                .unwrap_or_default();
+
+            let name = if item.bold {
+                format!("<b>{}</b>", escape(&food.name))
+            } else {
+                escape(&food.name)
+            };
+
+            format!(
+                "<li>{}{} {}{}</li>",
+                thumbnail,
+                name,
+                food.badges(),
+                calories
+            )
+        })
+        .collect::<String>();
+
+    format!("<ul>{}</ul>", items)
+}
+
+/// Builds an Atom feed with an entry for every served day from last week
+/// through next week. Entries are updated whenever FlikIsDining says the
+/// week's menu was. Weeks that can't be fetched are left out, it's only an
+/// error if none could be.
+pub async fn build() -> Result<String, FetchError> {
+    let mut set = JoinSet::new();
+    for n in FEED_WEEKS {
+        let date = Utc::now() + chrono::Duration::weeks(n);
+        set.spawn(async move { (date, flikisdining::fetch_week(date).await) });
+    }
+
+    // (day, when its week was last updated, if FlikIsDining said)
+    let mut days: Vec<(FlikIsDiningDay, Option<DateTime<Utc>>)> = Vec::new();
+    let mut failed: Option<FetchError> = None;
+    while let Some(res) = set.join_next().await {
+        match res {
+            Ok((_, Ok(week))) => {
+                let updated = week.last_updated.as_deref().and_then(parse_updated);
+                days.extend(week.days.into_iter().map(|day| (day, updated)));
+            }
+            Ok((date, Err(why))) => {
+                warn!(
+                    week = %date.format("%Y-%m-%d"),
+                    error = ?why,
+                    "Leaving a week out of the feed"
+                );
+                failed = Some(why);
+            }
+            Err(why) => error!("Error joining feed fetch: {:?}", why),
+        }
+    }
+
+    if let Some(why) = failed.filter(|_| days.is_empty()) {
+        return Err(why);
+    }
+
+    // newest first, and only days that had lunch
+    days.retain(|(day, _)| !day.menu_items.is_empty());
+    days.sort_by(|a, b| b.0.date.cmp(&a.0.date));
+    days.dedup_by(|a, b| a.0.date == b.0.date);
+
+    // (day, its date, when it was last updated)
+    let entries = days
+        .iter()
+        .filter_map(|(day, updated)| {
+            let date = NaiveDate::parse_from_str(&day.date, "%Y-%m-%d").ok()?;
+            Some((day, date, updated.unwrap_or_else(|| midnight(date))))
+        })
+        .collect::<Vec<_>>();
+
+    let feed_updated = entries
+        .iter()
+        .map(|(_, _, updated)| *updated)
+        .max()
+        .unwrap_or_else(|| midnight(config::get().today()));
+
+    let mut feed = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    feed.push_str("<feed xmlns=\"http://www.w3.org/2005/Atom\">\n");
+    feed.push_str("  <id>urn:lunchbotv2:menu</id>\n");
+    feed.push_str("  <title>Lunch</title>\n");
+    feed.push_str(&format!(
+        "  <updated>{}</updated>\n",
+        feed_updated.to_rfc3339()
+    ));
+    feed.push_str("  <author><name>lunchbotv2</name></author>\n");
+
+    for (day, date, updated) in &entries {
+        feed.push_str("  <entry>\n");
+        feed.push_str(&format!("    <id>urn:lunchbotv2:day:{}</id>\n", day.date));
+        feed.push_str(&format!(
+            "    <title>Lunch for {}</title>\n",
+            date.format("%A, %b %-d")
+        ));
+        feed.push_str(&format!(
+            "    <updated>{}</updated>\n",
+            updated.to_rfc3339()
+        ));
+        feed.push_str(&format!(
+            "    <content type=\"html\">{}</content>\n",
+            escape(&render_html(day))
+        ));
+        feed.push_str("  </entry>\n");
+    }
+
+    feed.push_str("</feed>\n");
+    Ok(feed)
+}
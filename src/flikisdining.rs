@@ -1,18 +1,31 @@
 // hide dead code warnings
 #![allow(dead_code)]
 
-use crate::env::SCHOOL_KEY;
+use crate::config::{self, SchoolConfig};
+use crate::{health, history, logging, metrics};
 use chrono::Datelike;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDate, Utc, Weekday};
+use futures::future::{BoxFuture, FutureExt, Shared};
 use http_cache_quickcache::QuickManager;
 use http_cache_reqwest::{Cache, CacheMode, HttpCache, HttpCacheOptions};
 use once_cell::sync::Lazy;
 use reqwest::Client;
 use reqwest_middleware::{ClientBuilder, ClientWithMiddleware};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{hash_map::RandomState, HashMap},
+    future::Future,
+    hash::BuildHasher,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Instant,
+};
 use thiserror::Error;
+use tracing::{debug, error, instrument, warn, Span};
 
-#[derive(Deserialize, Default, Clone, Debug)]
+#[derive(Serialize, Deserialize, Default, Clone, Debug)]
 pub struct FlikIsDiningNutritionInfo {
     pub calories: Option<f32>,
     pub raw_calories: Option<f32>,
@@ -37,13 +50,208 @@ pub struct FlikIsDiningNutritionInfo {
     pub mcg_vitamin_d: Option<f32>,
 }
 
-#[derive(Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct FlikIsDiningServingSizeInfo {
     pub serving_size_amount: String,
     pub serving_size_unit: String,
 }
 
-#[derive(Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct FlikIsDiningFoodIcon {
+    pub id: Option<f32>,
+    pub slug: Option<String>,
+    pub name: Option<String>,
+    pub synced_name: Option<String>,
+    pub help_text: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Default, Clone, Debug)]
+pub struct FlikIsDiningFoodIcons {
+    #[serde(default)]
+    pub food_icons: Vec<FlikIsDiningFoodIcon>,
+}
+
+/// Allergen and dietary tags, parsed from the food icons
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum FoodTag {
+    Vegetarian,
+    Vegan,
+    GlutenFree,
+    Halal,
+    Milk,
+    Egg,
+    Fish,
+    Shellfish,
+    TreeNuts,
+    Peanuts,
+    Wheat,
+    Soy,
+    Sesame,
+    Pork,
+}
+
+impl FoodTag {
+    pub const ALL: [FoodTag; 14] = [
+        FoodTag::Vegetarian,
+        FoodTag::Vegan,
+        FoodTag::GlutenFree,
+        FoodTag::Halal,
+        FoodTag::Milk,
+        FoodTag::Egg,
+        FoodTag::Fish,
+        FoodTag::Shellfish,
+        FoodTag::TreeNuts,
+        FoodTag::Peanuts,
+        FoodTag::Wheat,
+        FoodTag::Soy,
+        FoodTag::Sesame,
+        FoodTag::Pork,
+    ];
+
+    /// Matches a word, an icon name or an icon slug to a tag, ignoring case and
+    /// punctuation so `gluten-free`, `Gluten Free` and `gluten_free` are all the
+    /// same. Whole words only, so "eggplant" isn't an egg, and anything that says
+    /// it's free of something ("Nut Free", "Made without Gluten") isn't tagged
+    /// with it, gluten free being the only "free" tag there is.
+    pub fn from_word(word: &str) -> Option<FoodTag> {
+        let words = word
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .map(|word| word.to_lowercase())
+            .collect::<Vec<String>>();
+
+        let negated = words
+            .iter()
+            .any(|word| matches!(word.as_str(), "free" | "without" | "no" | "not"));
+        let joined = words.concat();
+
+        if joined == "glutenfree"
+            || joined == "gf"
+            || (negated && words.iter().any(|word| word == "gluten"))
+        {
+            return Some(FoodTag::GlutenFree);
+        }
+        if negated {
+            return None;
+        }
+
+        // slugs like `tree_nuts` are checked joined up, then each word on its own
+        std::iter::once(&joined)
+            .chain(&words)
+            .find_map(|word| match word.trim_end_matches('s') {
+                "vegan" => Some(FoodTag::Vegan),
+                "vegetarian" | "veggie" => Some(FoodTag::Vegetarian),
+                "halal" => Some(FoodTag::Halal),
+                "milk" | "dairy" => Some(FoodTag::Milk),
+                "egg" => Some(FoodTag::Egg),
+                "fish" => Some(FoodTag::Fish),
+                "shellfish" | "crustacean" => Some(FoodTag::Shellfish),
+                "treenut" | "nut" => Some(FoodTag::TreeNuts),
+                "peanut" => Some(FoodTag::Peanuts),
+                "wheat" | "gluten" => Some(FoodTag::Wheat),
+                "soy" | "soybean" => Some(FoodTag::Soy),
+                "sesame" => Some(FoodTag::Sesame),
+                "pork" => Some(FoodTag::Pork),
+                _ => None,
+            })
+    }
+
+    fn from_icon(icon: &FlikIsDiningFoodIcon) -> Option<FoodTag> {
+        [&icon.slug, &icon.synced_name, &icon.name]
+            .into_iter()
+            .flatten()
+            .find_map(|word| FoodTag::from_word(word))
+    }
+
+    /// A single lowercase word, used as the search index term
+    pub fn key(&self) -> &'static str {
+        match self {
+            FoodTag::Vegetarian => "vegetarian",
+            FoodTag::Vegan => "vegan",
+            FoodTag::GlutenFree => "glutenfree",
+            FoodTag::Halal => "halal",
+            FoodTag::Milk => "milk",
+            FoodTag::Egg => "egg",
+            FoodTag::Fish => "fish",
+            FoodTag::Shellfish => "shellfish",
+            FoodTag::TreeNuts => "treenuts",
+            FoodTag::Peanuts => "peanuts",
+            FoodTag::Wheat => "wheat",
+            FoodTag::Soy => "soy",
+            FoodTag::Sesame => "sesame",
+            FoodTag::Pork => "pork",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            FoodTag::Vegetarian => "Vegetarian",
+            FoodTag::Vegan => "Vegan",
+            FoodTag::GlutenFree => "Gluten Free",
+            FoodTag::Halal => "Halal",
+            FoodTag::Milk => "Milk",
+            FoodTag::Egg => "Egg",
+            FoodTag::Fish => "Fish",
+            FoodTag::Shellfish => "Shellfish",
+            FoodTag::TreeNuts => "Tree Nuts",
+            FoodTag::Peanuts => "Peanuts",
+            FoodTag::Wheat => "Wheat",
+            FoodTag::Soy => "Soy",
+            FoodTag::Sesame => "Sesame",
+            FoodTag::Pork => "Pork",
+        }
+    }
+
+    pub fn emoji(&self) -> &'static str {
+        match self {
+            FoodTag::Vegetarian => "🥕",
+            FoodTag::Vegan => "🌱",
+            FoodTag::GlutenFree => "🚫🌾",
+            FoodTag::Halal => "☪️",
+            FoodTag::Milk => "🥛",
+            FoodTag::Egg => "🥚",
+            FoodTag::Fish => "🐟",
+            FoodTag::Shellfish => "🦐",
+            FoodTag::TreeNuts => "🌰",
+            FoodTag::Peanuts => "🥜",
+            FoodTag::Wheat => "🌾",
+            FoodTag::Soy => "🫘",
+            FoodTag::Sesame => "⚪",
+            FoodTag::Pork => "🐖",
+        }
+    }
+
+    /// a short text badge, for places emoji can't be drawn (like the week image)
+    pub fn code(&self) -> &'static str {
+        match self {
+            FoodTag::Vegetarian => "V",
+            FoodTag::Vegan => "VG",
+            FoodTag::GlutenFree => "GF",
+            FoodTag::Halal => "H",
+            FoodTag::Milk => "M",
+            FoodTag::Egg => "E",
+            FoodTag::Fish => "F",
+            FoodTag::Shellfish => "SF",
+            FoodTag::TreeNuts => "TN",
+            FoodTag::Peanuts => "P",
+            FoodTag::Wheat => "W",
+            FoodTag::Soy => "S",
+            FoodTag::Sesame => "SE",
+            FoodTag::Pork => "PK",
+        }
+    }
+
+    /// whether this tag means the food contains something (rather than being free of it)
+    pub fn is_allergen(&self) -> bool {
+        !matches!(
+            self,
+            FoodTag::Vegetarian | FoodTag::Vegan | FoodTag::GlutenFree | FoodTag::Halal
+        )
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct FlikIsDiningFood {
     pub id: f32,
     pub name: String,
@@ -52,9 +260,32 @@ pub struct FlikIsDiningFood {
     // pub synced_ingredients: Option<String>,
     pub rounded_nutrition_info: Option<FlikIsDiningNutritionInfo>,
     pub serving_size_info: Option<FlikIsDiningServingSizeInfo>,
+    pub icons: Option<FlikIsDiningFoodIcons>,
+}
+
+impl FlikIsDiningFood {
+    /// the allergen and dietary tags on this food, without duplicates
+    pub fn tags(&self) -> Vec<FoodTag> {
+        let mut tags: Vec<FoodTag> = Vec::new();
+
+        for icon in self.icons.iter().flat_map(|icons| &icons.food_icons) {
+            if let Some(tag) = FoodTag::from_icon(icon) {
+                if !tags.contains(&tag) {
+                    tags.push(tag);
+                }
+            }
+        }
+
+        tags
+    }
+
+    /// the tags as a compact string of emoji, e.g. `🌱🥜`
+    pub fn badges(&self) -> String {
+        self.tags().iter().map(FoodTag::emoji).collect()
+    }
 }
 
-#[derive(Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct FlikIsDiningMenuItem {
     pub id: f32,
     pub position: f32,
@@ -63,9 +294,16 @@ pub struct FlikIsDiningMenuItem {
     pub image: Option<String>,
     pub image_thumbnail: Option<String>,
     pub food: Option<FlikIsDiningFood>,
+    #[serde(default)]
+    pub is_station_header: bool,
+    #[serde(default)]
+    pub is_section_title: bool,
+    /// the station this item is served at, filled in from the header above it
+    #[serde(default)]
+    pub station: Option<String>,
 }
 
-#[derive(Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct FlikIsDiningDay {
     /// yyyy-mm-dd
     pub date: String,
@@ -74,7 +312,7 @@ pub struct FlikIsDiningDay {
     pub menu_items: Vec<FlikIsDiningMenuItem>,
 }
 
-#[derive(Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct FlikIsDiningResponse {
     pub start_date: Option<String>,
     pub menu_type_id: Option<f32>,
@@ -101,40 +339,268 @@ pub enum FetchError {
     #[error("No lunch found for date {0}")]
     NoLunchForDate(String),
 
+    #[error("Menu not published yet for date {0}")]
+    MenuNotPublished(String),
+
     #[error("Received non-success status code: {0}")]
     HttpStatusError(reqwest::StatusCode),
+
+    /// the error from a fetch that several callers shared
+    #[error(transparent)]
+    Shared(Arc<FetchError>),
 }
 
+/// counts reported errors, so no two get the same id
+static REPORTED: AtomicU64 = AtomicU64::new(0);
+
+impl FetchError {
+    /// What went wrong, in words for the people asking for lunch.
+    pub fn friendly(&self) -> String {
+        match self {
+            FetchError::Shared(why) => why.friendly(),
+            FetchError::NoLunchForDate(date) | FetchError::MenuNotPublished(date) => {
+                let Ok(date) = NaiveDate::parse_from_str(date, "%Y-%m-%d") else {
+                    return "There's no lunch on the menu for that day.".to_owned();
+                };
+
+                match (self, date.weekday()) {
+                    (_, Weekday::Sat | Weekday::Sun) => {
+                        format!(
+                            "No lunch on {}, the cafeteria is closed on weekends.",
+                            date.format("%A")
+                        )
+                    }
+                    (FetchError::MenuNotPublished(_), _) => format!(
+                        "The menu for {} isn't published yet, check back later.",
+                        date.format("%A, %b %-d")
+                    ),
+                    _ => format!(
+                        "There's no lunch on the menu for {}.",
+                        date.format("%A, %b %-d")
+                    ),
+                }
+            }
+            FetchError::HttpStatusError(status) if status.is_client_error() => {
+                "The cafeteria site doesn't have that menu, try again later.".to_owned()
+            }
+            FetchError::RequestFailed(_)
+            | FetchError::RequestMiddlewareFailed(_)
+            | FetchError::BodyReadFailed(_)
+            | FetchError::HttpStatusError(_) => {
+                "The cafeteria site is down, try again in a bit.".to_owned()
+            }
+            FetchError::JsonParseFailed(_) => {
+                "The cafeteria site sent back a menu I can't read.".to_owned()
+            }
+        }
+    }
+
+    /// Logs the error under a short id and returns the friendly message with
+    /// the same id, so a report in discord can be found in the log. The raw
+    /// error is only included when `debug_errors` is on.
+    pub fn report(&self) -> String {
+        let id = RandomState::new().hash_one(REPORTED.fetch_add(1, Ordering::Relaxed)) & 0xFFFFFF;
+        let id = format!("{:06x}", id);
+
+        error!(error_id = %id, error = ?self, "Error fetching lunch");
+
+        let mut message = format!("{} (error `{}`)", self.friendly(), id);
+        if config::get().debug_errors {
+            message += &format!("\n```\n{:?}\n```", self);
+        }
+
+        message
+    }
+}
+
+/// a week fetch that everyone asking for the same week at once waits on
+type SharedFetch = Shared<BoxFuture<'static, Result<FlikIsDiningResponse, Arc<FetchError>>>>;
+
+/// fetches that haven't finished yet, by URL and whether they skip the cache
+static IN_FLIGHT: Lazy<Mutex<HashMap<(String, bool), SharedFetch>>> = Lazy::new(Default::default);
+
+/// the HTTP cache, kept here so `/admin` can look at it and clear it
+static MANAGER: Lazy<QuickManager> = Lazy::new(QuickManager::default);
+
 // create the http client
 static CLIENT: Lazy<ClientWithMiddleware> = Lazy::new(|| {
-    ClientBuilder::new(Client::new())
+    let cache = &config::get().cache;
+    let builder = ClientBuilder::new(Client::new());
+
+    if !cache.enabled {
+        return builder.build();
+    }
+
+    let mode = match cache.mode {
+        config::CacheMode::Default => CacheMode::Default,
+        config::CacheMode::NoStore => CacheMode::NoStore,
+        config::CacheMode::Reload => CacheMode::Reload,
+        config::CacheMode::NoCache => CacheMode::NoCache,
+        config::CacheMode::ForceCache => CacheMode::ForceCache,
+        config::CacheMode::OnlyIfCached => CacheMode::OnlyIfCached,
+        config::CacheMode::IgnoreRules => CacheMode::IgnoreRules,
+    };
+
+    builder
         .with(Cache(HttpCache {
-            mode: CacheMode::Default,
-            manager: QuickManager::default(),
+            mode,
+            manager: MANAGER.clone(),
             options: HttpCacheOptions::default(),
         }))
         .build()
 });
 
-pub async fn fetch_week_lunch(date: DateTime<Utc>) -> Result<Vec<FlikIsDiningDay>, FetchError> {
+/// Fetches the whole week response (including `last_updated`), with only the
+/// food items left in each day.
+pub async fn fetch_week(date: DateTime<Utc>) -> Result<FlikIsDiningResponse, FetchError> {
+    fetch_school_week(&config::get().school, date).await
+}
+
+/// Same as [`fetch_week`], for any school. Only the default school's menus
+/// go into the menu history.
+pub async fn fetch_school_week(
+    school: &SchoolConfig,
+    date: DateTime<Utc>,
+) -> Result<FlikIsDiningResponse, FetchError> {
+    fetch(school, date, false).await
+}
+
+/// Same as [`fetch_week`], but always asks FlikIsDining instead of using the
+/// HTTP cache, and updates the cache with the answer.
+pub async fn refetch_week(date: DateTime<Utc>) -> Result<FlikIsDiningResponse, FetchError> {
+    refetch_school_week(&config::get().school, date).await
+}
+
+/// Same as [`refetch_week`], for any school.
+pub async fn refetch_school_week(
+    school: &SchoolConfig,
+    date: DateTime<Utc>,
+) -> Result<FlikIsDiningResponse, FetchError> {
+    fetch(school, date, true).await
+}
+
+/// How many responses are in the HTTP cache.
+pub fn cache_entries() -> usize {
+    MANAGER.cache.len()
+}
+
+/// Empties the HTTP cache, returning how many responses were in it.
+pub fn clear_cache() -> usize {
+    let entries = MANAGER.cache.len();
+    MANAGER.cache.clear();
+    entries
+}
+
+/// The FlikIsDining URL for the week containing a date.
+fn url(school: &SchoolConfig, date: DateTime<Utc>) -> String {
+    format!(
+        "https://{}.api.flikisdining.com/menu/api/weeks/school/{}/menu-type/{}/{}/{}/{}/?format=json",
+        school.key, school.slug, school.menu_type, date.year(), date.month(), date.day()
+    )
+}
+
+/// Fetches a week and notes how it went, for `/admin diag`. If the same week
+/// is already being fetched, this waits for that instead of asking again,
+/// since the HTTP cache only helps once a response is stored.
+async fn fetch(
+    school: &SchoolConfig,
+    date: DateTime<Utc>,
+    reload: bool,
+) -> Result<FlikIsDiningResponse, FetchError> {
+    let school = school.clone();
+
+    single_flight((url(&school, date), reload), async move {
+        let result = request(&school, date, reload).await;
+        health::fetched(&school.slug, date.date_naive(), result.as_ref().err());
+        result
+    })
+    .await
+}
+
+/// Runs `request`, unless one for the same key is already running, in which
+/// case this waits for that one and gets the same result.
+async fn single_flight(
+    key: (String, bool),
+    request: impl Future<Output = Result<FlikIsDiningResponse, FetchError>> + Send + 'static,
+) -> Result<FlikIsDiningResponse, FetchError> {
+    let shared = IN_FLIGHT
+        .lock()
+        .unwrap()
+        .entry(key.clone())
+        .or_insert_with(|| {
+            async move {
+                let result = request.await;
+
+                // anyone asking after this starts a new fetch
+                IN_FLIGHT.lock().unwrap().remove(&key);
+                result.map_err(Arc::new)
+            }
+            .boxed()
+            .shared()
+        })
+        .clone();
+
+    if shared.strong_count().is_some_and(|count| count > 2) {
+        debug!("Joined a fetch already in progress");
+    }
+
+    // the last one waiting gets the error itself, the others share it
+    shared
+        .await
+        .map_err(|why| Arc::try_unwrap(why).unwrap_or_else(FetchError::Shared))
+}
+
+#[instrument(
+    name = "fetch",
+    skip_all,
+    fields(school = %school.slug, week = %date.format("%Y-%m-%d"), reload = reload, status)
+)]
+async fn request(
+    school: &SchoolConfig,
+    date: DateTime<Utc>,
+    reload: bool,
+) -> Result<FlikIsDiningResponse, FetchError> {
     // create the URL
-    let url = format!(
-        "https://{}.api.flikisdining.com/menu/api/weeks/school/kentucky-country-day-school/menu-type/lunch/{}/{}/{}/?format=json",
-        *SCHOOL_KEY, date.year(), date.month(), date.day()
-    );
+    let url = url(school, date);
 
-    println!("Fetching lunch from {}", url);
+    debug!("Fetching lunch from {}", url);
 
     // fetch the data
-    let response = CLIENT.get(&url).send().await?;
+    let started = Instant::now();
+    let mut request = CLIENT.get(&url);
+    if reload {
+        request = request.with_extension(CacheMode::Reload);
+    }
+
+    let response = match request.send().await {
+        Ok(response) => response,
+        Err(why) => {
+            metrics::FETCH_SECONDS
+                .with_label_values(&["error"])
+                .observe(started.elapsed().as_secs_f64());
+            return Err(why.into());
+        }
+    };
 
     let status = response.status();
+    Span::current().record("status", status.as_u16());
+    metrics::FETCH_SECONDS
+        .with_label_values(&[status.as_str()])
+        .observe(started.elapsed().as_secs_f64());
+
+    // the cache middleware says whether it answered from the cache
+    if let Some(cache) = response.headers().get("x-cache") {
+        let result = if cache.as_bytes().eq_ignore_ascii_case(b"hit") {
+            "hit"
+        } else {
+            "miss"
+        };
+        metrics::CACHE.with_label_values(&[result]).inc();
+    }
+
     if !status.is_success() {
-        eprintln!(
-            "Request failed with status: {}. Response text: {:?}",
-            status,
-            response.text().await.ok()
-        );
+        let body = response.text().await.unwrap_or_default();
+        warn!(%status, body = logging::cap(&body), "Request failed");
         return Err(FetchError::HttpStatusError(status));
     }
 
@@ -144,42 +610,204 @@ pub async fn fetch_week_lunch(date: DateTime<Utc>) -> Result<Vec<FlikIsDiningDay
     // Attempt to parse the text
     let response_data: FlikIsDiningResponse =
         serde_json::from_str(&response_text).map_err(|e| {
-            eprintln!("Failed to parse JSON: {}", e);
-            eprintln!("Response Text was:\n{}", response_text);
+            warn!(error = %e, body = logging::cap(&response_text), "Failed to parse JSON");
             FetchError::JsonParseFailed(e)
         })?;
 
-    // for each day, filter so only food items are left
+    // for each day, filter so only food items are left, remembering which
+    // station header each one was under
     let days = response_data
         .days
         .into_iter()
         .map(|day| {
+            let mut station: Option<String> = None;
             let menu_items = day
                 .menu_items
                 .into_iter()
-                .filter(|item| item.food.is_some())
+                .filter_map(|item| {
+                    if (item.is_station_header || item.is_section_title)
+                        && !item.text.trim().is_empty()
+                    {
+                        station = Some(item.text.trim().to_owned());
+                    }
+
+                    item.food.is_some().then(|| FlikIsDiningMenuItem {
+                        station: item.station.clone().or_else(|| station.clone()),
+                        ..item
+                    })
+                })
                 .collect::<Vec<FlikIsDiningMenuItem>>();
 
             FlikIsDiningDay { menu_items, ..day }
         })
         .collect::<Vec<FlikIsDiningDay>>();
 
+    // keep a copy in the menu history
+    if *school == config::get().school {
+        history::record(&days);
+    }
+
     // return the response
-    Ok(days)
+    Ok(FlikIsDiningResponse {
+        days,
+        ..response_data
+    })
+}
+
+pub async fn fetch_week_lunch(date: DateTime<Utc>) -> Result<Vec<FlikIsDiningDay>, FetchError> {
+    Ok(fetch_week(date).await?.days)
 }
 
 pub async fn fetch_lunch(date: DateTime<Utc>) -> Result<Vec<FlikIsDiningMenuItem>, FetchError> {
+    fetch_school_lunch(&config::get().school, date).await
+}
+
+/// Same as [`fetch_lunch`], for any school.
+pub async fn fetch_school_lunch(
+    school: &SchoolConfig,
+    date: DateTime<Utc>,
+) -> Result<Vec<FlikIsDiningMenuItem>, FetchError> {
     // get the week
-    let week = fetch_week_lunch(date).await?;
+    let week = fetch_school_week(school, date).await?.days;
 
     let date_str = date.format("%Y-%m-%d").to_string();
 
+    // an empty week means the menu isn't out yet
+    let published = week.iter().any(|day| !day.menu_items.is_empty());
+
     // find today's lunch
     let today = week.into_iter().find(|day| day.date == date_str);
 
     // if there was no lunch, return an error
     match today {
+        Some(day) if !day.menu_items.is_empty() => Ok(day.menu_items),
+        _ if matches!(date.weekday(), Weekday::Sat | Weekday::Sun) => {
+            Err(FetchError::NoLunchForDate(date_str))
+        }
+        _ if !published => Err(FetchError::MenuNotPublished(date_str)),
         Some(day) => Ok(day.menu_items),
         None => Err(FetchError::NoLunchForDate(date_str)),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{single_flight, FetchError, FlikIsDiningResponse, FoodTag, IN_FLIGHT};
+    use futures::future::join_all;
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    fn response() -> FlikIsDiningResponse {
+        FlikIsDiningResponse {
+            start_date: None,
+            menu_type_id: None,
+            days: Vec::new(),
+            last_updated: None,
+            id: None,
+            bold_all_entrees_enabled: None,
+        }
+    }
+
+    /// Asks for the same key `callers` times at once, counting the requests made.
+    async fn fetch_together(
+        key: &str,
+        callers: usize,
+        result: fn() -> Result<FlikIsDiningResponse, FetchError>,
+        requests: &Arc<AtomicUsize>,
+    ) -> Vec<Result<FlikIsDiningResponse, FetchError>> {
+        join_all((0..callers).map(|_| {
+            let requests = requests.clone();
+
+            single_flight((key.to_owned(), false), async move {
+                requests.fetch_add(1, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                result()
+            })
+        }))
+        .await
+    }
+
+    #[tokio::test]
+    async fn single_flight_shares_one_request() {
+        let key = "https://test/single-flight/shares";
+        let requests = Arc::new(AtomicUsize::new(0));
+
+        let results = fetch_together(key, 5, || Ok(response()), &requests).await;
+        assert!(results.iter().all(Result::is_ok));
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+        assert!(!IN_FLIGHT
+            .lock()
+            .unwrap()
+            .contains_key(&(key.to_owned(), false)));
+
+        // once it's done, the next caller asks again
+        fetch_together(key, 1, || Ok(response()), &requests).await;
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn single_flight_shares_errors() {
+        let key = "https://test/single-flight/errors";
+        let requests = Arc::new(AtomicUsize::new(0));
+
+        let results = fetch_together(
+            key,
+            3,
+            || Err(FetchError::NoLunchForDate("2026-10-19".to_owned())),
+            &requests,
+        )
+        .await;
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+        for result in results {
+            assert_eq!(
+                result.unwrap_err().to_string(),
+                "No lunch found for date 2026-10-19"
+            );
+        }
+        assert!(!IN_FLIGHT
+            .lock()
+            .unwrap()
+            .contains_key(&(key.to_owned(), false)));
+    }
+
+    #[test]
+    fn from_word_matches_names_and_slugs() {
+        assert_eq!(FoodTag::from_word("Vegan"), Some(FoodTag::Vegan));
+        assert_eq!(
+            FoodTag::from_word("Contains Tree Nuts"),
+            Some(FoodTag::TreeNuts)
+        );
+        assert_eq!(FoodTag::from_word("tree_nuts"), Some(FoodTag::TreeNuts));
+        assert_eq!(FoodTag::from_word("peanuts"), Some(FoodTag::Peanuts));
+        assert_eq!(FoodTag::from_word("Shellfish"), Some(FoodTag::Shellfish));
+        assert_eq!(FoodTag::from_word("Contains Milk"), Some(FoodTag::Milk));
+        assert_eq!(FoodTag::from_word("gluten-free"), Some(FoodTag::GlutenFree));
+        assert_eq!(FoodTag::from_word("Gluten Free"), Some(FoodTag::GlutenFree));
+        assert_eq!(FoodTag::from_word("glutenfree"), Some(FoodTag::GlutenFree));
+    }
+
+    #[test]
+    fn from_word_ignores_free_of_labels() {
+        assert_eq!(FoodTag::from_word("Nut Free"), None);
+        assert_eq!(FoodTag::from_word("nut_free"), None);
+        assert_eq!(FoodTag::from_word("Dairy Free"), None);
+        assert_eq!(FoodTag::from_word("no eggs"), None);
+        assert_eq!(
+            FoodTag::from_word("Made without Gluten-Containing Ingredients"),
+            Some(FoodTag::GlutenFree)
+        );
+    }
+
+    #[test]
+    fn from_word_matches_whole_words() {
+        assert_eq!(FoodTag::from_word("eggplant"), None);
+        assert_eq!(FoodTag::from_word("donut"), None);
+        assert_eq!(FoodTag::from_word("catfish"), None);
+        assert_eq!(FoodTag::from_word("Fried Egg"), Some(FoodTag::Egg));
+    }
+}
@@ -1,40 +1,288 @@
+use once_cell::sync::OnceCell;
 use serenity::{
+    all::{CreateInteractionResponse, CreateInteractionResponseMessage},
     async_trait,
-    model::prelude::Message,
+    gateway::{ConnectionStage, ShardStageUpdateEvent},
+    model::{
+        event::MessageUpdateEvent,
+        prelude::{ChannelId, GuildId, Interaction, Message, MessageId, Ready, UserId},
+    },
     prelude::{Context, EventHandler},
 };
 
-use crate::{env::PRIMARY_LUNCH_CHANNEL, lunch, search};
+use crate::{
+    admin, calendar,
+    config::{self, Command, Origin},
+    diet, health, history, ingredients, lunch, metrics, permissions, ratelimit, ratings, render,
+    reply, school, search, stats, warm, watch,
+};
+use tracing::{error, field, info, info_span, Instrument};
+
+/// the bot's own user, so mentions can be recognized (set once connected)
+static BOT_USER: OnceCell<UserId> = OnceCell::new();
+
+/// The message without a leading mention of the bot, if it starts with one.
+fn strip_mention(content: &str, me: UserId) -> Option<String> {
+    let content = content.trim_start();
+
+    [format!("<@{}>", me), format!("<@!{}>", me)]
+        .iter()
+        .find_map(|mention| content.strip_prefix(mention.as_str()))
+        .map(|rest| rest.trim().to_owned())
+}
+
+/// Runs the command a message asks for, if any. Returns false if it asked
+/// for one but was turned away for its level. Re-runs of edited messages
+/// aren't rate limited, the edit isn't a new request.
+async fn dispatch(context: Context, mut msg: Message, rerun: bool) -> bool {
+    if msg.author.bot {
+        return true;
+    }
+
+    let config = config::get();
+    let me = BOT_USER.get().copied();
+
+    // only a leading mention counts as talking to the bot, not one in the
+    // middle of a sentence or a reply ping
+    let rest = me.and_then(|me| strip_mention(&msg.content, me));
+
+    let origin = Origin {
+        primary: config.is_primary(msg.channel_id),
+        dm: msg.guild_id.is_none(),
+        mention: rest.is_some(),
+    };
+
+    // drop the mention, so "@LunchBot friday" is parsed like "friday"
+    if let Some(rest) = rest {
+        msg.content = rest;
+    }
+
+    // get the message content so we can match on it
+    let content = msg.content.to_lowercase();
+
+    // find the first rule that matches (see `triggers` in the config),
+    // anything else in a DM asks for lunch, and so does a mention with just a day
+    let command = match config.trigger(&content, &origin) {
+        Some(rule) => rule.command,
+        None if origin.dm => Command::Lunch,
+        None if origin.mention && lunch::is_just_a_day(&content) => Command::Lunch,
+        None => return true,
+    };
+
+    // commands above everyone's level are refused with a reaction
+    let required = config.required_level(command);
+    let level = permissions::for_message(&context.http, &msg, required).await;
+    if level < required {
+        permissions::log_denied(&msg.author.name, command.name(), level, required);
+
+        if let Err(why) = msg.react(&context.http, '🚫').await {
+            error!("Error reacting: {:?}", why);
+        }
+
+        return false;
+    }
+
+    // slow down anyone asking too often, without replying
+    if !rerun && !ratelimit::allow(msg.author.id, msg.channel_id, command) {
+        info!("Rate limited {} ({:?})", msg.author.name, command);
+
+        if let Err(why) = msg.react(&context.http, '⏳').await {
+            error!("Error reacting: {:?}", why);
+        }
+
+        return false;
+    }
+
+    // everything logged while handling the command is tagged with where it came
+    // from, the date is filled in by commands that resolve one
+    let span = info_span!(
+        "command",
+        ?command,
+        guild = msg.guild_id.map(|guild| guild.get()),
+        channel = msg.channel_id.get(),
+        user = %msg.author.name,
+        date = field::Empty,
+    );
+
+    metrics::COMMANDS.with_label_values(&[command.name()]).inc();
+
+    let handle = async move {
+        match command {
+            Command::Lunch => lunch::handle(context, msg).await,
+            Command::Search => search::handle(context, msg).await,
+            Command::Ingredients => ingredients::handle(context, msg).await,
+            Command::Diet => diet::handle(context, msg).await,
+            Command::Watch => watch::handle(context, msg).await,
+            Command::Ratings => ratings::handle(context, msg).await,
+            Command::Stats => stats::handle(context, msg).await,
+            Command::Calendar => calendar::handle(context, msg).await,
+            Command::Week => render::handle(context, msg).await,
+            Command::School => school::handle(context, msg).await,
+        }
+    };
+
+    handle.instrument(span).await;
+    true
+}
 
 pub struct Handler;
 
 #[async_trait]
 impl EventHandler for Handler {
     async fn message(&self, context: Context, msg: Message) {
-        if msg.author.bot {
+        dispatch(context, msg, false).await;
+    }
+
+    async fn message_update(
+        &self,
+        context: Context,
+        _old: Option<Message>,
+        _new: Option<Message>,
+        event: MessageUpdateEvent,
+    ) {
+        // only content edits to messages the bot answered recently matter
+        if event.content.is_none() || !reply::is_tracked(event.id) {
             return;
         }
 
-        // get the message content so we can match on it
-        let content = msg.content.to_lowercase();
+        let mut msg = match event.channel_id.message(&context.http, event.id).await {
+            Ok(msg) => msg,
+            Err(why) => {
+                error!("Error fetching edited message: {:?}", why);
+                return;
+            }
+        };
 
-        // check for `what` and `lunch` (if in env `PRIMARY_LUNCH_CHANNEL`)
-        // otherwise, check if it is "what lunch"
-        if (msg.channel_id.to_string() == *PRIMARY_LUNCH_CHANNEL
-            && content.contains("what")
-            && content.contains("lunch"))
-            || (content.contains("what lunch"))
-        {
-            lunch::handle(context, msg).await;
-            return;
+        // fetched messages don't say which server they're in or who sent them
+        // there, and without their roles the permission check turns them away
+        msg.guild_id = event.guild_id;
+        if let Some(guild) = event.guild_id {
+            match guild.member(&context.http, msg.author.id).await {
+                Ok(member) => msg.member = Some(Box::new(member.into())),
+                Err(why) => {
+                    error!("Error fetching member of edited message: {:?}", why);
+                    return;
+                }
+            }
+        }
+
+        // run it again, editing the old replies instead of sending new ones
+        if reply::begin_rerun(msg.id) {
+            info!("Re-running edited message from {}", msg.author.name);
+
+            // if the edit was turned away, the old replies are left as they were
+            if dispatch(context.clone(), msg, true).await {
+                reply::finish_rerun(&context, event.channel_id, event.id).await;
+            } else {
+                reply::cancel_rerun(event.channel_id, event.id);
+            }
+        }
+    }
+
+    async fn message_delete(
+        &self,
+        context: Context,
+        _channel_id: ChannelId,
+        deleted_message_id: MessageId,
+        _guild_id: Option<GuildId>,
+    ) {
+        reply::delete(&context, deleted_message_id).await;
+    }
+
+    async fn message_delete_bulk(
+        &self,
+        context: Context,
+        _channel_id: ChannelId,
+        multiple_deleted_messages_ids: Vec<MessageId>,
+        _guild_id: Option<GuildId>,
+    ) {
+        for id in multiple_deleted_messages_ids {
+            reply::delete(&context, id).await;
+        }
+    }
+
+    async fn ready(&self, context: Context, ready: Ready) {
+        info!("Connected as {}", ready.user.name);
+
+        health::set_connected(true);
+
+        let shard = ready.shard.map(|shard| shard.id.0).unwrap_or(0);
+        metrics::GATEWAY_CONNECTED
+            .with_label_values(&[&shard.to_string()])
+            .set(1);
+
+        let _ = BOT_USER.set(ready.user.id);
+
+        // slash commands
+        admin::register(&context.http).await;
+        permissions::register(&context.http).await;
+
+        let features = &config::get().features;
+
+        // start checking for watched foods
+        if features.watch {
+            watch::start(context.http.clone());
         }
 
-        // check if starts with "when will we have"
-        // check if starts with "when will we have"
-        if msg.channel_id.to_string() == *PRIMARY_LUNCH_CHANNEL
-            && content.starts_with("when will we have")
-        {
-            search::handle(context, msg).await;
+        // and fill in the menu history
+        if features.history_backfill {
+            history::backfill();
+        }
+
+        // and keep the menus fresh before lunch
+        warm::start();
+    }
+
+    async fn shard_stage_update(&self, _context: Context, event: ShardStageUpdateEvent) {
+        let connected = event.new == ConnectionStage::Connected;
+        health::set_connected(connected);
+
+        metrics::GATEWAY_CONNECTED
+            .with_label_values(&[&event.shard_id.0.to_string()])
+            .set(i64::from(connected));
+    }
+
+    async fn interaction_create(&self, context: Context, interaction: Interaction) {
+        match interaction {
+            Interaction::Component(component)
+                if config::get().features.ratings && ratings::is_rating_component(&component) =>
+            {
+                ratings::handle_component(context, component).await;
+            }
+            Interaction::Command(command) => {
+                let required = match command.data.name.as_str() {
+                    "admin" => admin::level(&command),
+                    "permissions" => permissions::LEVEL,
+                    _ => return,
+                };
+
+                let level = permissions::for_interaction(&command);
+                if level < required {
+                    permissions::log_denied(
+                        &command.user.name,
+                        &command.data.name,
+                        level,
+                        required,
+                    );
+
+                    let response = CreateInteractionResponse::Message(
+                        CreateInteractionResponseMessage::new()
+                            .content("You don't have permission to use this.")
+                            .ephemeral(true),
+                    );
+                    if let Err(why) = command.create_response(&context.http, response).await {
+                        error!("Error responding to /{}: {:?}", command.data.name, why);
+                    }
+
+                    return;
+                }
+
+                match command.data.name.as_str() {
+                    "admin" => admin::handle(context, command).await,
+                    _ => permissions::handle(context, command).await,
+                }
+            }
+            _ => {}
         }
     }
 }
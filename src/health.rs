@@ -0,0 +1,145 @@
+use crate::flikisdining::FetchError;
+use axum::{http::StatusCode, response::IntoResponse, routing::get, Json, Router};
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicU8, Ordering},
+        Mutex,
+    },
+};
+
+/// how many weeks of fetch results to remember
+const KEEP_WEEKS: usize = 30;
+
+/// the gateway isn't used, e.g. when only running `serve`
+const GATEWAY_DISABLED: u8 = 0;
+const GATEWAY_DISCONNECTED: u8 = 1;
+const GATEWAY_CONNECTED: u8 = 2;
+
+static GATEWAY: AtomicU8 = AtomicU8::new(GATEWAY_DISABLED);
+
+/// when a menu was last fetched from FlikIsDining without errors
+static LAST_FETCH: Lazy<Mutex<Option<DateTime<Utc>>>> = Lazy::new(|| Mutex::new(None));
+
+static STARTED: Lazy<DateTime<Utc>> = Lazy::new(Utc::now);
+
+/// How the last fetch of a week went.
+#[derive(Clone, Debug)]
+pub struct WeekFetch {
+    pub at: DateTime<Utc>,
+    /// the error, or `None` if it worked
+    pub error: Option<String>,
+}
+
+/// the last fetch of each week, by school slug and the week's monday
+static WEEKS: Lazy<Mutex<BTreeMap<(NaiveDate, String), WeekFetch>>> =
+    Lazy::new(|| Mutex::new(BTreeMap::new()));
+
+/// Notes that the process started now, for the uptime.
+pub fn mark_started() {
+    Lazy::force(&STARTED);
+}
+
+pub fn started() -> DateTime<Utc> {
+    *STARTED
+}
+
+/// Notes whether the bot is connected to the Discord gateway.
+pub fn set_connected(connected: bool) {
+    GATEWAY.store(
+        if connected {
+            GATEWAY_CONNECTED
+        } else {
+            GATEWAY_DISCONNECTED
+        },
+        Ordering::Relaxed,
+    );
+}
+
+/// Notes how fetching a school's week just went.
+pub fn fetched(school: &str, date: NaiveDate, error: Option<&FetchError>) {
+    let now = Utc::now();
+    if error.is_none() {
+        *LAST_FETCH.lock().unwrap() = Some(now);
+    }
+
+    let monday = date - chrono::Duration::days(i64::from(date.weekday().num_days_from_monday()));
+    let mut weeks = WEEKS.lock().unwrap();
+    weeks.insert(
+        (monday, school.to_owned()),
+        WeekFetch {
+            at: now,
+            error: error.map(ToString::to_string),
+        },
+    );
+
+    // forget the oldest weeks
+    while weeks.len() > KEEP_WEEKS {
+        weeks.pop_first();
+    }
+}
+
+/// The last fetch of each week, newest week last.
+pub fn week_fetches() -> Vec<(NaiveDate, String, WeekFetch)> {
+    WEEKS
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|((monday, school), fetch)| (*monday, school.clone(), fetch.clone()))
+        .collect()
+}
+
+/// Whether the bot is connected to the gateway, `None` if it isn't running.
+pub fn connected() -> Option<bool> {
+    match GATEWAY.load(Ordering::Relaxed) {
+        GATEWAY_CONNECTED => Some(true),
+        GATEWAY_DISCONNECTED => Some(false),
+        _ => None,
+    }
+}
+
+#[derive(Serialize)]
+struct Health {
+    status: &'static str,
+    /// `connected`, `disconnected`, or `disabled` when the bot isn't running
+    gateway: &'static str,
+    last_fetch: Option<DateTime<Utc>>,
+}
+
+fn health(ready: bool) -> Health {
+    Health {
+        status: if ready { "ok" } else { "unavailable" },
+        gateway: match connected() {
+            Some(true) => "connected",
+            Some(false) => "disconnected",
+            None => "disabled",
+        },
+        last_fetch: *LAST_FETCH.lock().unwrap(),
+    }
+}
+
+/// `GET /healthz`, the process is up and answering
+async fn healthz() -> impl IntoResponse {
+    Json(health(true))
+}
+
+/// `GET /readyz`, 503 while the bot isn't connected to the gateway
+async fn readyz() -> impl IntoResponse {
+    let ready = connected() != Some(false);
+    let status = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+
+    (status, Json(health(ready)))
+}
+
+pub fn router() -> Router {
+    Router::new()
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+}
@@ -0,0 +1,125 @@
+use crate::config;
+use crate::flikisdining::{self, FlikIsDiningDay, FoodTag};
+use crate::store::JsonStore;
+use chrono::{Datelike, NaiveDate, Utc, Weekday};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    sync::atomic::{AtomicBool, Ordering},
+};
+use tracing::warn;
+
+/// A menu item as it's kept in the archive, just the parts worth remembering
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct HistoryItem {
+    pub id: u64,
+    pub name: String,
+    pub calories: Option<f32>,
+    pub bold: bool,
+    #[serde(default)]
+    pub tags: Vec<FoodTag>,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+struct History {
+    /// menu items, keyed by date
+    days: BTreeMap<NaiveDate, Vec<HistoryItem>>,
+}
+
+static HISTORY: Lazy<JsonStore<History>> = Lazy::new(|| JsonStore::open("history"));
+
+/// so reconnects don't start a second backfill
+static BACKFILL_STARTED: AtomicBool = AtomicBool::new(false);
+
+/// Adds a fetched week to the archive, replacing whatever was there for those days.
+pub fn record(week: &[FlikIsDiningDay]) {
+    let days = week
+        .iter()
+        .filter(|day| !day.menu_items.is_empty())
+        .filter_map(|day| {
+            let date = NaiveDate::parse_from_str(&day.date, "%Y-%m-%d").ok()?;
+            let items = day
+                .menu_items
+                .iter()
+                .filter_map(|item| {
+                    let food = item.food.as_ref()?;
+
+                    Some(HistoryItem {
+                        id: food.id as u64,
+                        name: food.name.clone(),
+                        calories: food
+                            .rounded_nutrition_info
+                            .as_ref()
+                            .and_then(|info| info.calories),
+                        bold: item.bold,
+                        tags: food.tags(),
+                    })
+                })
+                .collect::<Vec<HistoryItem>>();
+
+            Some((date, items))
+        })
+        .collect::<Vec<(NaiveDate, Vec<HistoryItem>)>>();
+
+    // only write to disk if something actually changed
+    let changed = HISTORY.read(|history| {
+        days.iter()
+            .any(|(date, items)| history.days.get(date) != Some(items))
+    });
+
+    if changed {
+        HISTORY.update(|history| history.days.extend(days));
+    }
+}
+
+pub fn weekday_name(weekday: Weekday) -> &'static str {
+    match weekday {
+        Weekday::Mon => "Monday",
+        Weekday::Tue => "Tuesday",
+        Weekday::Wed => "Wednesday",
+        Weekday::Thu => "Thursday",
+        Weekday::Fri => "Friday",
+        Weekday::Sat => "Saturday",
+        Weekday::Sun => "Sunday",
+    }
+}
+
+/// Every archived day, oldest first.
+pub fn days() -> BTreeMap<NaiveDate, Vec<HistoryItem>> {
+    HISTORY.read(|history| history.days.clone())
+}
+
+/// Fetches the past `history_backfill_weeks` weeks in the background so a new
+/// archive has something to work with. Weeks that are already archived are skipped.
+pub fn backfill() {
+    if BACKFILL_STARTED.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    tokio::spawn(async move {
+        for n in 1..=config::get().history_backfill_weeks {
+            let date = Utc::now() - chrono::Duration::weeks(n);
+
+            // skip weeks we already have something for
+            let monday = date.date_naive()
+                - chrono::Duration::days(i64::from(date.weekday().num_days_from_monday()));
+            let archived = HISTORY.read(|history| {
+                history
+                    .days
+                    .range(monday..monday + chrono::Duration::days(7))
+                    .next()
+                    .is_some()
+            });
+
+            if archived {
+                continue;
+            }
+
+            // fetching records the week
+            if let Err(why) = flikisdining::fetch_week_lunch(date).await {
+                warn!("Error backfilling week of {}: {:?}", monday, why);
+            }
+        }
+    });
+}
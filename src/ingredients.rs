@@ -0,0 +1,316 @@
+use crate::config::{self, Command};
+use crate::reply::{self, Reply};
+use crate::{flikisdining, lunch, school, watch};
+use chrono::Utc;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serenity::{
+    all::CreateEmbedFooter, builder::CreateEmbed, model::prelude::Message, prelude::Context,
+};
+use tracing::{error, field, info, Span};
+
+/// discord caps embed field values at 1024 characters
+const MAX_FIELD_LENGTH: usize = 1024;
+
+/// and embeds at 25 fields
+const MAX_FIELDS: usize = 25;
+
+/// words that describe the date rather than the dish
+static DATE_WORDS: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"\b(today|tmr|tomorrow|monday|tues(day)?|wed(nesday)?|thurs(day)?|fri(day)?|\d{4}-\d{2}-\d{2})\b")
+        .unwrap()
+});
+
+static ISO_DATE: Lazy<Regex> = Lazy::new(|| Regex::new(r"\b\d{4}-\d{2}-\d{2}\b").unwrap());
+
+/// Splits the arguments into the words that describe the date and the dish,
+/// so "fried rice friday" is ("friday", "fried rice") and "french fries" has
+/// no date words at all.
+fn split_date(args: &str) -> (String, String) {
+    let date = DATE_WORDS
+        .find_iter(args)
+        .map(|word| word.as_str())
+        .collect::<Vec<&str>>()
+        .join(" ");
+    let dish = DATE_WORDS
+        .replace_all(args, "")
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ");
+
+    (date, dish)
+}
+
+/// Splits an ingredient list on the commas that aren't inside parentheses, so
+/// "Flour (Wheat, Niacin), Water" stays as two ingredients.
+fn split_ingredients(ingredients: &str) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    let mut depth = 0;
+
+    for c in ingredients.chars() {
+        match c {
+            '(' | '[' => depth += 1,
+            ')' | ']' => depth -= 1,
+            ',' if depth <= 0 => {
+                parts.push(current.trim().to_owned());
+                current.clear();
+                continue;
+            }
+            _ => {}
+        }
+
+        current.push(c);
+    }
+
+    parts.push(current.trim().to_owned());
+    parts.into_iter().filter(|part| !part.is_empty()).collect()
+}
+
+/// Wraps any of the watch terms found in the ingredient in bold.
+fn highlight(ingredient: &str, terms: &[String]) -> String {
+    if terms.is_empty() {
+        return ingredient.to_owned();
+    }
+
+    let pattern = terms
+        .iter()
+        .map(|term| regex::escape(term))
+        .collect::<Vec<String>>()
+        .join("|");
+
+    Regex::new(&format!("(?i)({})", pattern))
+        .map(|re| re.replace_all(ingredient, "**$1**").into_owned())
+        .unwrap_or(ingredient.to_owned())
+}
+
+/// Packs the ingredients into as few embed fields as possible.
+fn chunk_fields(ingredients: Vec<String>) -> Vec<String> {
+    let mut fields: Vec<String> = Vec::new();
+    let mut current = String::new();
+
+    for ingredient in ingredients {
+        // a single ingredient longer than a field gets cut off
+        let ingredient = if ingredient.len() > MAX_FIELD_LENGTH - 3 {
+            let mut end = MAX_FIELD_LENGTH - 3;
+            while !ingredient.is_char_boundary(end) {
+                end -= 1;
+            }
+            format!("{}...", &ingredient[..end])
+        } else {
+            ingredient
+        };
+
+        if !current.is_empty() && current.len() + ingredient.len() + 2 > MAX_FIELD_LENGTH {
+            fields.push(current);
+            current = String::new();
+        }
+
+        if !current.is_empty() {
+            current.push_str(", ");
+        }
+        current.push_str(&ingredient);
+    }
+
+    if !current.is_empty() {
+        fields.push(current);
+    }
+
+    fields.truncate(MAX_FIELDS);
+    fields
+}
+
+pub async fn handle(context: Context, msg: Message) {
+    // ignore bots
+    if msg.author.bot {
+        return;
+    }
+
+    // save start time so we can calculate processing time
+    let start = Utc::now();
+
+    // get the message content so we can match on it
+    let content = msg.content.to_lowercase();
+
+    // find the content after the ingredients trigger ("ingredients")
+    let args = config::get()
+        .argument(Command::Ingredients, &content)
+        .unwrap_or_default();
+    let args = args.as_str();
+
+    // figure out the date, either an explicit yyyy-mm-dd or the same words
+    // `what lunch` uses, only looking at whole date words so "french fries"
+    // isn't friday
+    let (date_words, dish) = split_date(args);
+    let date = ISO_DATE
+        .find(&date_words)
+        .and_then(|date| config::get().parse_date(date.as_str()))
+        .unwrap_or_else(|| lunch::resolve_date(&date_words).1);
+
+    // whatever is left over is the dish
+    let words = dish.split_whitespace().collect::<Vec<&str>>();
+
+    // if nothing, return
+    if words.is_empty() {
+        let _ = reply::send(
+            &context,
+            &msg,
+            Reply::new().content("Usage: `ingredients <dish> [date]`"),
+        )
+        .await;
+        return;
+    }
+
+    Span::current().record("date", field::display(date.format("%Y-%m-%d")));
+    info!(dish = words.join(" "), "Looking up ingredients");
+
+    // fetch lunch for that day
+    let lunch = match flikisdining::fetch_school_lunch(&school::for_user(msg.author.id), date).await
+    {
+        Ok(lunch) => lunch,
+        Err(why) => {
+            if let Err(why) = reply::send(&context, &msg, Reply::new().content(why.report())).await
+            {
+                error!("Error sending message: {:?}", why);
+            }
+
+            return;
+        }
+    };
+
+    // pick the item whose name contains the most of the search words
+    let food = lunch
+        .into_iter()
+        .filter_map(|item| item.food)
+        .map(|food| {
+            let name = food.name.to_lowercase();
+            let score = words.iter().filter(|word| name.contains(*word)).count();
+            (score, food)
+        })
+        .filter(|(score, _)| *score > 0)
+        .max_by_key(|(score, _)| *score)
+        .map(|(_, food)| food);
+
+    let Some(food) = food else {
+        let _ = reply::send(
+            &context,
+            &msg,
+            Reply::new().content(format!(
+                "Couldn't find `{}` on the menu for {}",
+                words.join(" "),
+                date.format("%A, %b %-d")
+            )),
+        )
+        .await;
+        return;
+    };
+
+    let mut embed = CreateEmbed::new()
+        .title(format!("🧾 {}", food.name))
+        .description(format!("Ingredients for {}", date.format("%A, %b %-d")))
+        .footer(CreateEmbedFooter::new(
+            (Utc::now() - start).num_milliseconds().to_string() + " ms",
+        ))
+        .color(0xEE8B2F)
+        .timestamp(Utc::now());
+
+    match food.ingredients.as_deref().map(str::trim) {
+        Some(ingredients) if !ingredients.is_empty() => {
+            // highlight both the server wide terms and the user's watchlist
+            let mut terms = config::get().ingredient_watch_terms.clone();
+            terms.extend(watch::terms_for(msg.author.id));
+
+            let ingredients = split_ingredients(ingredients)
+                .into_iter()
+                .map(|ingredient| highlight(&ingredient, &terms))
+                .collect();
+
+            for (i, field) in chunk_fields(ingredients).into_iter().enumerate() {
+                embed = embed.field(
+                    if i == 0 {
+                        "Ingredients"
+                    } else {
+                        "Ingredients (cont.)"
+                    },
+                    field,
+                    false,
+                );
+            }
+        }
+        _ => {
+            embed = embed.field("Ingredients", "No ingredient list published", false);
+        }
+    }
+
+    // and try to send the message
+    if let Err(why) = reply::send(&context, &msg, Reply::new().embed(embed)).await {
+        error!("Error sending message: {:?}", why);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{chunk_fields, highlight, split_date, split_ingredients, MAX_FIELD_LENGTH};
+
+    #[test]
+    fn split_date_only_takes_whole_date_words() {
+        assert_eq!(
+            split_date("french fries"),
+            ("".to_owned(), "french fries".to_owned())
+        );
+        assert_eq!(
+            split_date("potato wedges"),
+            ("".to_owned(), "potato wedges".to_owned())
+        );
+        assert_eq!(
+            split_date("fried rice friday"),
+            ("friday".to_owned(), "fried rice".to_owned())
+        );
+        assert_eq!(
+            split_date("pizza 2026-10-23"),
+            ("2026-10-23".to_owned(), "pizza".to_owned())
+        );
+    }
+
+    #[test]
+    fn split_ingredients_keeps_parentheses_together() {
+        assert_eq!(
+            split_ingredients("Flour (Wheat, Niacin), Water, , Salt [Iodized, Sea]"),
+            vec!["Flour (Wheat, Niacin)", "Water", "Salt [Iodized, Sea]"]
+        );
+    }
+
+    #[test]
+    fn highlight_bolds_terms_case_insensitively() {
+        assert_eq!(
+            highlight("Peanut Oil", &["peanut".to_owned()]),
+            "**Peanut** Oil"
+        );
+        assert_eq!(highlight("Salt", &[]), "Salt");
+        assert_eq!(
+            highlight("Salt (a.b)", &["a.b".to_owned()]),
+            "Salt (**a.b**)"
+        );
+    }
+
+    #[test]
+    fn chunk_fields_packs_and_cuts_off() {
+        assert_eq!(
+            chunk_fields(vec!["Water".to_owned(), "Salt".to_owned()]),
+            vec!["Water, Salt"]
+        );
+
+        let long = "é".repeat(MAX_FIELD_LENGTH);
+        let fields = chunk_fields(vec!["Water".to_owned(), long]);
+        assert_eq!(fields.len(), 2);
+        assert_eq!(fields[0], "Water");
+        assert!(fields[1].len() <= MAX_FIELD_LENGTH);
+        assert!(fields[1].ends_with("..."));
+    }
+
+    #[test]
+    fn chunk_fields_stops_at_the_field_limit() {
+        let ingredients = vec!["a".repeat(MAX_FIELD_LENGTH - 3); 30];
+        assert_eq!(chunk_fields(ingredients).len(), super::MAX_FIELDS);
+    }
+}
@@ -0,0 +1,35 @@
+use crate::config::{self, LogFormat};
+use tracing_subscriber::{fmt::format::FmtSpan, EnvFilter};
+
+/// Starts logging to stderr, in the format and at the level from the config.
+/// Each span is logged when it closes, with how long it took.
+pub fn init() {
+    let logging = &config::get().logging;
+
+    // the level was already checked when the config was loaded
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::new(&logging.level))
+        .with_span_events(FmtSpan::CLOSE)
+        .with_writer(std::io::stderr);
+
+    match logging.format {
+        LogFormat::Text => builder.init(),
+        LogFormat::Pretty => builder.pretty().init(),
+        LogFormat::Json => builder.json().init(),
+    }
+}
+
+/// The start of a response body, so a huge one doesn't flood the log.
+pub fn cap(body: &str) -> String {
+    let max = config::get().logging.max_body_bytes;
+    if body.len() <= max {
+        return body.to_owned();
+    }
+
+    let mut end = max;
+    while !body.is_char_boundary(end) {
+        end -= 1;
+    }
+
+    format!("{}... ({} more bytes)", &body[..end], body.len() - end)
+}
@@ -1,14 +1,12 @@
-use crate::env::PRIMARY_LUNCH_CHANNEL;
-use crate::flikisdining;
-use chrono::{Datelike, Utc};
+use crate::reply::{self, Reply};
+use crate::{config, diet, flikisdining, ratings, school};
+use chrono::{DateTime, Datelike, Utc};
 use once_cell::sync::Lazy;
 use regex::Regex;
 use serenity::{
-    all::CreateEmbedFooter,
-    builder::{CreateEmbed, CreateMessage},
-    model::prelude::Message,
-    prelude::Context,
+    all::CreateEmbedFooter, builder::CreateEmbed, model::prelude::Message, prelude::Context,
 };
+use tracing::{error, field, Span};
 
 static WEEKDAYS: Lazy<Vec<Regex>> = Lazy::new(|| {
     vec![
@@ -20,6 +18,50 @@ static WEEKDAYS: Lazy<Vec<Regex>> = Lazy::new(|| {
     ]
 });
 
+/// a message that's only a day, or nothing at all (today)
+static JUST_A_DAY: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^(?:today|tmr|tomorrow|monday|tues(?:day)?|wed(?:nesday)?|thurs(?:day)?|fri(?:day)?)?[?!.]*$")
+        .unwrap()
+});
+
+/// Whether a message is only a day, like "friday" or "tmr?", so a bare
+/// "@LunchBot friday" can ask for lunch without a trigger.
+pub fn is_just_a_day(content: &str) -> bool {
+    JUST_A_DAY.is_match(content.trim())
+}
+
+/// Figures out which day a message is asking about, based on any weekday
+/// names and `tmr`/`tomorrow` mentions. Returns the offset in days and the date.
+pub fn resolve_date(content: &str) -> (i64, DateTime<Utc>) {
+    let config = config::get();
+    let today = config.today();
+
+    // if a weekday is mentioned, create an offset from today to that weekday
+    let mut days: i64 = 0;
+    for (i, weekday) in WEEKDAYS.iter().enumerate() {
+        if weekday.is_match(content) {
+            days = i64::try_from(i).unwrap();
+            days -= i64::from(today.weekday().num_days_from_monday());
+            break;
+        }
+    }
+
+    // if the days matched is negative, that means it's already passed
+    // offset by 7 days so we get the next week
+    if days < 0 {
+        days += 7;
+    }
+
+    // for each `tmr` or `tomorrow` in the content, add a day
+    days += i64::try_from(content.matches("tmr").count() + content.matches("tomorrow").count())
+        .unwrap();
+
+    let date = today + chrono::Duration::days(days);
+    Span::current().record("date", field::display(date));
+
+    (days, config.midday(date))
+}
+
 pub async fn handle(context: Context, msg: Message) {
     // ignore bots
     if msg.author.bot {
@@ -32,138 +74,114 @@ pub async fn handle(context: Context, msg: Message) {
     // get the message content so we can match on it
     let content = msg.content.to_lowercase();
 
-    // check for `what` and `lunch` (if in env `PRIMARY_LUNCH_CHANNEL`)
-    // otherwise, check if it is "what lunch"
-    if (msg.channel_id.to_string() == *PRIMARY_LUNCH_CHANNEL
-        && content.contains("what")
-        && content.contains("lunch"))
-        || (content.contains("what lunch"))
-    {
-        // debug mode
-        let debug = content.contains("whats in your head");
-
-        // figure out date
-        let mut date = Utc::now();
-
-        // if a weekday is mentioned, create an offset from today to that weekday
-        let mut days: i64 = 0;
-        for (i, weekday) in WEEKDAYS.iter().enumerate() {
-            if weekday.is_match(&content) {
-                days = i64::try_from(i).unwrap();
-                days -= i64::try_from(date.weekday().num_days_from_monday()).unwrap();
-                break;
-            }
-        }
+    let features = &config::get().features;
 
-        // if the days matched is negative, that means it's already passed
-        // offset by 7 days so we get the next week
-        if days < 0 {
-            days += 7;
-        }
+    // figure out date
+    let (days, date) = resolve_date(&content);
 
-        // for each `tmr` or `tomorrow` in the content, add a day
-        days += i64::try_from(content.matches("tmr").count() + content.matches("tomorrow").count())
-            .unwrap();
-        date = date + chrono::Duration::days(days);
-
-        // debug log the amount of days added
-        if debug {
-            let _ = msg.channel_id.say(
-                &context.http,
-                format!(
-                    "[debug] Days added: {} | Date: {}",
-                    days,
-                    date.format("%Y-%m-%d")
-                ),
-            );
+    // fetch lunch for that day
+    let lunch = flikisdining::fetch_school_lunch(&school::for_user(msg.author.id), date).await;
+
+    // if there was an error, send a message to the channel
+    if let Err(why) = lunch {
+        // attempt to send in the channel
+        if let Err(why) = reply::send(&context, &msg, Reply::new().content(why.report())).await {
+            error!("Error sending message: {:?}", why);
         }
 
-        // fetch lunch for that day
-        let lunch = flikisdining::fetch_lunch(date).await;
-
-        // if there was an error, send a message to the channel
-        if let Err(why) = lunch {
-            println!("Error fetching lunch: {:?}", why);
-
-            // attempt to send in the channel
-            if let Err(why) = msg
-                .channel_id
-                .say(
-                    &context.http,
-                    // "Failed to fetch lunch: ".to_owned() + &message,
-                    format!("Failed to fetch lunch: {:?}", why),
-                )
-                .await
-            {
-                println!("Error sending message: {:?}", why);
+        return;
+    }
+
+    // get the lunch
+    let lunch = lunch.unwrap();
+    let mut thumbnail: Option<String> = None;
+
+    // keep track of the dish names so they can be rated
+    if features.ratings {
+        ratings::remember(&lunch);
+    }
+
+    // get the menu items
+    let menu_items = lunch
+        .iter()
+        .map(|item| {
+            let food = item.food.clone().unwrap();
+            let badges = food.badges();
+
+            // get the calories
+            let cals = food
+                .rounded_nutrition_info
+                .unwrap_or_default()
+                .calories
+                .unwrap_or(-1.0);
+
+            // check if this has a thumbnail
+            if item.image_thumbnail.is_some() {
+                // if it does, set the thumbnail
+                thumbnail = item.image_thumbnail.clone();
             }
 
-            return;
+            // add the dietary badges after the name, if there are any
+            let name = if badges.is_empty() {
+                food.name
+            } else {
+                format!("{} {}", food.name, badges)
+            };
+
+            // and the average rating, if anyone has rated it
+            let rating = ratings::average(msg.guild_id, food.id)
+                .filter(|_| features.ratings)
+                .map(|(average, _)| format!(" - ⭐ {:.1}", average))
+                .unwrap_or_default();
+
+            // return the formatted string
+            format!(
+                "{} - `{}` cals{}",
+                name,
+                if cals == -1.0 {
+                    "".to_owned()
+                } else {
+                    cals.to_string()
+                },
+                rating
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("\n");
+
+    // and try to send the message
+    if let Err(why) = reply::send(&context, &msg, {
+        let mut embed = CreateEmbed::new()
+            .title(match days {
+                0 => "🍖 Today's Lunch".to_owned(),
+                1 => "🍖 Tomorrow's Lunch".to_owned(),
+                days => format!("🍖 Lunch in {} days", days),
+            })
+            .description(menu_items)
+            .footer(CreateEmbedFooter::new(
+                (Utc::now() - start).num_milliseconds().to_string() + " ms",
+            ))
+            .color(0xEE8B2F)
+            .timestamp(Utc::now());
+
+        if let Some(thumbnail) = thumbnail {
+            embed = embed.thumbnail(thumbnail);
         }
 
-        // get the lunch
-        let lunch = lunch.unwrap();
-        let mut thumbnail: Option<String> = None;
-
-        // get the menu items
-        let menu_items = lunch
-            .into_iter()
-            .map(|item| {
-                let food = item.food.unwrap();
-
-                // get the calories
-                let cals = food
-                    .rounded_nutrition_info
-                    .unwrap_or_default()
-                    .calories
-                    .unwrap_or(-1.0);
-
-                // check if this has a thumbnail
-                if item.image_thumbnail.is_some() {
-                    // if it does, set the thumbnail
-                    thumbnail = item.image_thumbnail;
-                }
-
-                // return the formatted string
-                format!(
-                    "{} - `{}` cals",
-                    food.name,
-                    if cals == -1.0 {
-                        "".to_owned()
-                    } else {
-                        cals.to_string()
-                    }
-                )
-            })
-            .collect::<Vec<String>>()
-            .join("\n");
-
-        // and try to send the message
-        if let Err(why) = msg
-            .channel_id
-            .send_message(&context.http, {
-                let mut embed = CreateEmbed::new()
-                    .title(match days {
-                        0 => "🍖 Today's Lunch".to_owned(),
-                        1 => "🍖 Tomorrow's Lunch".to_owned(),
-                        days => format!("🍖 Lunch in {} days", days),
-                    })
-                    .description(menu_items)
-                    .footer(CreateEmbedFooter::new(
-                        (Utc::now() - start).num_milliseconds().to_string() + " ms",
-                    ))
-                    .color(0xEE8B2F)
-                    .timestamp(Utc::now());
-
-                if thumbnail.is_some() {
-                    embed = embed.thumbnail(thumbnail.unwrap());
-                }
-
-                CreateMessage::new().embed(embed)
-            })
-            .await
-        {
-            println!("Error sending message: {:?}", why);
+        let message = Reply::new().embed(embed);
+        if features.ratings {
+            message.components(ratings::components(&lunch))
+        } else {
+            message
         }
+    })
+    .await
+    {
+        error!("Error sending message: {:?}", why);
+    }
+
+    // let the user know if anything doesn't fit their diet profile
+    if features.diet {
+        diet::warn_user(&context, &msg.author, &lunch, date).await;
     }
 }
@@ -1,22 +1,107 @@
-mod env;
+mod admin;
+mod api;
+mod calendar;
+mod cli;
+mod config;
+mod diet;
+mod feed;
 mod flikisdining;
 mod handler;
+mod health;
+mod history;
+mod ingredients;
+mod logging;
 mod lunch;
+mod metrics;
+mod permissions;
+mod predict;
+mod ratelimit;
+mod ratings;
+mod render;
+mod reply;
+mod school;
 mod search;
+mod shutdown;
+mod stats;
+mod store;
+mod warm;
+mod watch;
 
 use serenity::{prelude::GatewayIntents, Client};
-use std::env as std_env;
+use std::env;
+use tracing::{error, info, warn};
 
 #[tokio::main]
 async fn main() {
     // load env
     let _ = dotenvy::dotenv();
 
+    // anything other than running the bot doesn't need discord
+    let command = match cli::parse(env::args().skip(1)) {
+        Ok(command) => command,
+        Err(why) => {
+            eprintln!("{}\nRun `lunchbotv2 help` for usage", why);
+            std::process::exit(2);
+        }
+    };
+
+    // load and check the config, so mistakes show up now instead of on the first fetch
+    if command != cli::Command::Help {
+        if let Err(why) = config::init() {
+            eprintln!("Invalid config: {}", why);
+            std::process::exit(2);
+        }
+
+        logging::init();
+        health::mark_started();
+    }
+
+    if command != cli::Command::Bot {
+        if let Err(why) = cli::run(command).await {
+            eprintln!("Error: {}", why);
+            std::process::exit(1);
+        }
+
+        return;
+    }
+
+    let config = config::get();
+
     // get the token
-    let token = std_env::var("TOKEN").expect("Expected a token in the environment");
+    let Some(token) = config.token.clone() else {
+        eprintln!(
+            "Invalid config: {}",
+            config::ConfigError::Missing {
+                name: "token",
+                env: "TOKEN"
+            }
+        );
+        std::process::exit(2);
+    };
+
+    // pick up config changes without reconnecting
+    config::watch();
+
+    if config.channels.primary.is_empty() {
+        warn!("No primary channels configured, only \"what lunch\" will work");
+    }
+
+    // start the JSON API, if enabled
+    if let Some(addr) = config.http_bind {
+        tokio::spawn(async move {
+            if let Err(why) = api::serve(addr).await {
+                error!("HTTP API error: {:?}", why);
+            }
+        });
+    }
+
+    // and the metrics, if enabled
+    metrics::start();
 
     // set the intents
-    let intents = GatewayIntents::GUILD_MESSAGES | GatewayIntents::MESSAGE_CONTENT;
+    let intents = GatewayIntents::GUILD_MESSAGES
+        | GatewayIntents::DIRECT_MESSAGES
+        | GatewayIntents::MESSAGE_CONTENT;
 
     // create the client
     let mut client = Client::builder(&token, intents)
@@ -24,8 +109,20 @@ async fn main() {
         .await
         .expect("Failed to create client");
 
+    // disconnect cleanly on SIGINT or SIGTERM, which makes `start` return
+    let shard_manager = client.shard_manager.clone();
+    tokio::spawn(async move {
+        shutdown::signal().await;
+        shard_manager.shutdown_all().await;
+    });
+
     // start the client
+    health::set_connected(false);
     if let Err(why) = client.start().await {
-        println!("Client error: {:?}", why);
+        error!("Client error: {:?}", why);
     }
+
+    // make sure everything is saved before exiting
+    store::flush();
+    info!("Shut down");
 }
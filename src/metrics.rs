@@ -0,0 +1,145 @@
+use crate::{config, health};
+use axum::{http::header, response::IntoResponse, routing::get, Router};
+use once_cell::sync::Lazy;
+use prometheus::{
+    Encoder, Histogram, HistogramOpts, HistogramVec, IntCounterVec, IntGaugeVec, Opts, Registry,
+    TextEncoder,
+};
+use std::net::SocketAddr;
+use tracing::{error, info};
+
+static REGISTRY: Lazy<Registry> = Lazy::new(Registry::new);
+
+/// Makes a metric and adds it to the registry, the names are fixed so this can't fail.
+fn register<T: prometheus::core::Collector + Clone + 'static>(metric: T) -> T {
+    REGISTRY
+        .register(Box::new(metric.clone()))
+        .expect("metric names should be unique");
+    metric
+}
+
+/// commands run, by command
+pub static COMMANDS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register(
+        IntCounterVec::new(
+            Opts::new("lunchbot_commands_total", "Commands run, by command"),
+            &["command"],
+        )
+        .unwrap(),
+    )
+});
+
+/// how long FlikIsDining requests take, by HTTP status (`error` if there wasn't one)
+pub static FETCH_SECONDS: Lazy<HistogramVec> = Lazy::new(|| {
+    register(
+        HistogramVec::new(
+            HistogramOpts::new(
+                "lunchbot_fetch_duration_seconds",
+                "FlikIsDining request latency, by HTTP status",
+            ),
+            &["status"],
+        )
+        .unwrap(),
+    )
+});
+
+/// FlikIsDining responses the HTTP cache answered (`hit`) or had to fetch (`miss`)
+pub static CACHE: Lazy<IntCounterVec> = Lazy::new(|| {
+    register(
+        IntCounterVec::new(
+            Opts::new(
+                "lunchbot_http_cache_total",
+                "FlikIsDining responses by HTTP cache result",
+            ),
+            &["result"],
+        )
+        .unwrap(),
+    )
+});
+
+/// how long building the search index takes
+pub static INDEX_SECONDS: Lazy<Histogram> = Lazy::new(|| {
+    register(
+        Histogram::with_opts(HistogramOpts::new(
+            "lunchbot_search_index_duration_seconds",
+            "Time spent building the search index",
+        ))
+        .unwrap(),
+    )
+});
+
+/// messages that couldn't be sent, by what was being done (`send`, `edit` or `dm`)
+pub static SEND_FAILURES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register(
+        IntCounterVec::new(
+            Opts::new(
+                "lunchbot_discord_send_failures_total",
+                "Discord messages that failed to send",
+            ),
+            &["action"],
+        )
+        .unwrap(),
+    )
+});
+
+/// 1 while a shard is connected to the gateway, 0 otherwise
+pub static GATEWAY_CONNECTED: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register(
+        IntGaugeVec::new(
+            Opts::new(
+                "lunchbot_gateway_connected",
+                "Whether the shard is connected to the Discord gateway",
+            ),
+            &["shard"],
+        )
+        .unwrap(),
+    )
+});
+
+/// Every metric in the Prometheus text format.
+pub fn render() -> String {
+    // make sure every metric shows up, even before it's first used
+    Lazy::force(&COMMANDS);
+    Lazy::force(&FETCH_SECONDS);
+    Lazy::force(&CACHE);
+    Lazy::force(&INDEX_SECONDS);
+    Lazy::force(&SEND_FAILURES);
+    Lazy::force(&GATEWAY_CONNECTED);
+
+    let mut buffer = Vec::new();
+    if let Err(why) = TextEncoder::new().encode(&REGISTRY.gather(), &mut buffer) {
+        error!("Error encoding metrics: {:?}", why);
+    }
+
+    String::from_utf8(buffer).unwrap_or_default()
+}
+
+/// `GET /metrics`
+async fn metrics() -> impl IntoResponse {
+    ([(header::CONTENT_TYPE, prometheus::TEXT_FORMAT)], render())
+}
+
+/// Serves `/metrics` until the process exits.
+async fn serve(addr: SocketAddr) -> std::io::Result<()> {
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    info!("Metrics listening on {}", addr);
+
+    let router = Router::new()
+        .route("/metrics", get(metrics))
+        .merge(health::router());
+
+    axum::serve(listener, router).await
+}
+
+/// Serves the metrics in the background, if `metrics_bind` is set.
+pub fn start() {
+    let Some(addr) = config::get().metrics_bind else {
+        return;
+    };
+
+    tokio::spawn(async move {
+        if let Err(why) = serve(addr).await {
+            error!("Metrics error: {:?}", why);
+        }
+    });
+}
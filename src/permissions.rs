@@ -0,0 +1,292 @@
+use crate::config::{self, Level};
+use crate::store::JsonStore;
+use once_cell::sync::Lazy;
+use serenity::{
+    all::{
+        Command, CommandInteraction, CommandOptionType, CreateCommand, CreateCommandOption,
+        CreateInteractionResponse, CreateInteractionResponseMessage, ResolvedOption, ResolvedValue,
+    },
+    builder::CreateEmbed,
+    http::Http,
+    model::prelude::{GuildId, Message, RoleId, UserId},
+    prelude::Context,
+};
+use std::collections::HashMap;
+use tracing::{error, info, warn};
+
+/// the level needed to change who has which level
+pub const LEVEL: Level = Level::Admin;
+
+/// levels granted to roles with `/permissions`, by guild id and then role id
+static GRANTS: Lazy<JsonStore<HashMap<u64, HashMap<u64, Level>>>> =
+    Lazy::new(|| JsonStore::open("permissions"));
+
+fn level_name(level: Level) -> &'static str {
+    match level {
+        Level::Everyone => "everyone",
+        Level::Moderator => "moderator",
+        Level::Admin => "admin",
+    }
+}
+
+/// The highest level any of the roles gives. Server admins are always bot
+/// admins, and outside a server (in DMs) there are no roles.
+fn level(guild: Option<GuildId>, roles: &[RoleId], server_admin: bool) -> Level {
+    if server_admin {
+        return Level::Admin;
+    }
+
+    let Some(guild) = guild else {
+        return Level::Everyone;
+    };
+
+    let config = config::get();
+    let permissions = &config.permissions;
+
+    GRANTS.read(|grants| {
+        let granted = grants.get(&guild.get());
+
+        roles
+            .iter()
+            .map(|role| {
+                let role = role.get();
+
+                if permissions.admin_roles.contains(&role) {
+                    Level::Admin
+                } else if permissions.moderator_roles.contains(&role) {
+                    Level::Moderator
+                } else {
+                    granted
+                        .and_then(|granted| granted.get(&role))
+                        .copied()
+                        .unwrap_or_default()
+                }
+            })
+            .max()
+            .unwrap_or_default()
+    })
+}
+
+/// Whether someone owns the server or has a role with Administrator,
+/// including the server's @everyone role.
+async fn is_server_admin(http: &Http, guild: GuildId, user: UserId, roles: &[RoleId]) -> bool {
+    let guild = match guild.to_partial_guild(http).await {
+        Ok(guild) => guild,
+        Err(why) => {
+            warn!("Error fetching server to check permissions: {:?}", why);
+            return false;
+        }
+    };
+
+    guild.owner_id == user
+        || roles
+            .iter()
+            .chain([RoleId::new(guild.id.get())].iter())
+            .filter_map(|role| guild.roles.get(role))
+            .any(|role| role.permissions.administrator())
+}
+
+/// The level of whoever sent a message, enough to tell whether it's at least
+/// `required`. Messages don't say whether someone is a server admin, so the
+/// server is only fetched to check when their roles aren't enough.
+pub async fn for_message(http: &Http, msg: &Message, required: Level) -> Level {
+    let roles = msg
+        .member
+        .as_ref()
+        .map(|member| member.roles.as_slice())
+        .unwrap_or_default();
+
+    let level = level(msg.guild_id, roles, false);
+    if level >= required {
+        return level;
+    }
+
+    match msg.guild_id {
+        Some(guild) if is_server_admin(http, guild, msg.author.id, roles).await => Level::Admin,
+        _ => level,
+    }
+}
+
+/// The level of whoever used a slash command.
+pub fn for_interaction(command: &CommandInteraction) -> Level {
+    let Some(member) = &command.member else {
+        return Level::Everyone;
+    };
+
+    let server_admin = member
+        .permissions
+        .is_some_and(|permissions| permissions.administrator());
+
+    level(command.guild_id, &member.roles, server_admin)
+}
+
+/// Logs that someone tried to run something above their level.
+pub fn log_denied(user: &str, command: &str, level: Level, required: Level) {
+    warn!(
+        user,
+        command,
+        level = level_name(level),
+        required = level_name(required),
+        "Permission denied"
+    );
+}
+
+/// Registers `/permissions` with discord.
+pub async fn register(http: &Http) {
+    let role = |description: &str| {
+        CreateCommandOption::new(CommandOptionType::Role, "role", description).required(true)
+    };
+
+    let command = CreateCommand::new("permissions")
+        .description("Give roles bot moderator or bot admin")
+        .add_option(
+            CreateCommandOption::new(
+                CommandOptionType::SubCommand,
+                "grant",
+                "Give a role a level",
+            )
+            .add_sub_option(role("The role to give the level to"))
+            .add_sub_option(
+                CreateCommandOption::new(CommandOptionType::String, "level", "The level")
+                    .required(true)
+                    .add_string_choice("moderator", "moderator")
+                    .add_string_choice("admin", "admin"),
+            ),
+        )
+        .add_option(
+            CreateCommandOption::new(
+                CommandOptionType::SubCommand,
+                "revoke",
+                "Take a role's level away",
+            )
+            .add_sub_option(role("The role to take the level from")),
+        )
+        .add_option(CreateCommandOption::new(
+            CommandOptionType::SubCommand,
+            "list",
+            "Show which roles have which level",
+        ));
+
+    if let Err(why) = Command::create_global_command(http, command).await {
+        error!("Error registering /permissions: {:?}", why);
+    }
+}
+
+/// Every role with a level in the server, one line each.
+fn list(guild: GuildId) -> String {
+    let config = config::get();
+    let permissions = &config.permissions;
+
+    let mut lines = vec!["Server admins: admin".to_owned()];
+    lines.extend(
+        permissions
+            .admin_roles
+            .iter()
+            .map(|role| format!("<@&{}>: admin (config)", role)),
+    );
+    lines.extend(
+        permissions
+            .moderator_roles
+            .iter()
+            .map(|role| format!("<@&{}>: moderator (config)", role)),
+    );
+
+    GRANTS.read(|grants| {
+        if let Some(granted) = grants.get(&guild.get()) {
+            lines.extend(
+                granted
+                    .iter()
+                    .map(|(role, level)| format!("<@&{}>: {}", role, level_name(*level))),
+            );
+        }
+    });
+
+    lines.join("\n")
+}
+
+pub async fn handle(context: Context, command: CommandInteraction) {
+    let reply = match command.guild_id {
+        None => "Permissions only work in a server.".to_owned(),
+        Some(guild) => match command.data.options().as_slice() {
+            [ResolvedOption {
+                name: "grant",
+                value: ResolvedValue::SubCommand(options),
+                ..
+            }] => match options.as_slice() {
+                [ResolvedOption {
+                    value: ResolvedValue::Role(role),
+                    ..
+                }, ResolvedOption {
+                    value: ResolvedValue::String(level),
+                    ..
+                }] => {
+                    let level = if *level == "admin" {
+                        Level::Admin
+                    } else {
+                        Level::Moderator
+                    };
+
+                    GRANTS.update(|grants| {
+                        grants
+                            .entry(guild.get())
+                            .or_default()
+                            .insert(role.id.get(), level)
+                    });
+                    info!(
+                        role = role.id.get(),
+                        level = level_name(level),
+                        "{} granted a level",
+                        command.user.name
+                    );
+
+                    format!("<@&{}> is now bot {}.", role.id, level_name(level))
+                }
+                _ => "Usage: `/permissions grant <role> <level>`".to_owned(),
+            },
+            [ResolvedOption {
+                name: "revoke",
+                value: ResolvedValue::SubCommand(options),
+                ..
+            }] => match options.as_slice() {
+                [ResolvedOption {
+                    value: ResolvedValue::Role(role),
+                    ..
+                }] => {
+                    let removed = GRANTS.update(|grants| {
+                        grants
+                            .get_mut(&guild.get())
+                            .and_then(|granted| granted.remove(&role.id.get()))
+                    });
+                    info!(
+                        role = role.id.get(),
+                        "{} revoked a level", command.user.name
+                    );
+
+                    match removed {
+                        Some(level) => {
+                            format!("<@&{}> isn't bot {} anymore.", role.id, level_name(level))
+                        }
+                        None => format!("<@&{}> didn't have a level.", role.id),
+                    }
+                }
+                _ => "Usage: `/permissions revoke <role>`".to_owned(),
+            },
+            _ => list(guild),
+        },
+    };
+
+    let embed = CreateEmbed::new()
+        .title("🔐 Permissions")
+        .description(reply)
+        .color(0xEE8B2F);
+
+    let response = CreateInteractionResponse::Message(
+        CreateInteractionResponseMessage::new()
+            .embed(embed)
+            .ephemeral(true),
+    );
+
+    if let Err(why) = command.create_response(&context.http, response).await {
+        error!("Error responding to /permissions: {:?}", why);
+    }
+}
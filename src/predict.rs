@@ -0,0 +1,205 @@
+use crate::history::{self, weekday_name};
+use chrono::{Datelike, NaiveDate, Weekday};
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// rotations to try, in weeks
+const PERIODS: std::ops::RangeInclusive<i64> = 1..=6;
+
+/// A guess at when a dish will be served next, based on the menu history
+#[derive(Serialize, Clone, Debug)]
+pub struct Prediction {
+    pub name: String,
+    pub date: NaiveDate,
+
+    /// 0 to 1
+    pub confidence: f32,
+
+    /// human readable description of the pattern, e.g. "every 2 weeks on Friday"
+    pub pattern: String,
+}
+
+impl Prediction {
+    pub fn confidence_label(&self) -> &'static str {
+        match self.confidence {
+            c if c >= 0.7 => "high",
+            c if c >= 0.4 => "medium",
+            _ => "low",
+        }
+    }
+}
+
+/// Looks for a recurring pattern in the dates a dish was served and projects it
+/// past `after` (usually the last published day). Needs at least 3 servings.
+pub fn predict(term: &str, after: NaiveDate) -> Option<Prediction> {
+    let words = term
+        .to_lowercase()
+        .split_whitespace()
+        .map(str::to_owned)
+        .collect::<Vec<String>>();
+
+    if words.is_empty() {
+        return None;
+    }
+
+    // every date the dish was served, and the most common name it went by
+    let mut dates: Vec<NaiveDate> = Vec::new();
+    let mut names: HashMap<String, usize> = HashMap::new();
+    for (date, items) in history::days() {
+        let matching = items.iter().find(|item| {
+            let name = item.name.to_lowercase();
+            words.iter().all(|word| name.contains(word))
+        });
+
+        if let Some(item) = matching {
+            dates.push(date);
+            *names.entry(item.name.clone()).or_default() += 1;
+        }
+    }
+
+    let name = names
+        .into_iter()
+        .max_by_key(|(_, count)| *count)
+        .map(|(name, _)| name)?;
+
+    project(name, &dates, after)
+}
+
+/// Finds the rotation in the (sorted) dates a dish was served and projects it past `after`.
+fn project(name: String, dates: &[NaiveDate], after: NaiveDate) -> Option<Prediction> {
+    if dates.len() < 3 {
+        return None;
+    }
+
+    let gaps = dates
+        .windows(2)
+        .map(|pair| (pair[1] - pair[0]).num_days())
+        .collect::<Vec<i64>>();
+    let last = *dates.last().unwrap();
+
+    // with less history, be less sure of anything
+    let sample_factor = (gaps.len() as f32 / 4.0).min(1.0);
+
+    // score each rotation length: full credit for gaps that match exactly, half
+    // credit for multiples (a holiday or a skipped week)
+    let best = PERIODS
+        .map(|weeks| {
+            let period = weeks * 7;
+            let score = gaps
+                .iter()
+                .map(|gap| {
+                    if *gap == period {
+                        1.0
+                    } else if gap % period == 0 {
+                        0.5
+                    } else {
+                        0.0
+                    }
+                })
+                .sum::<f32>()
+                / gaps.len() as f32;
+
+            (weeks, score)
+        })
+        .max_by(|a, b| a.1.total_cmp(&b.1));
+
+    if let Some((weeks, score)) = best.filter(|(_, score)| *score >= 0.5) {
+        // step the rotation forward from the last serving until it's past the horizon
+        let period = chrono::Duration::weeks(weeks);
+        let mut date = last + period;
+        while date <= after {
+            date += period;
+        }
+
+        let pattern = if weeks == 1 {
+            format!("every {}", weekday_name(last.weekday()))
+        } else {
+            format!("every {} weeks on {}", weeks, weekday_name(last.weekday()))
+        };
+
+        return Some(Prediction {
+            name,
+            date,
+            confidence: score * sample_factor,
+            pattern,
+        });
+    }
+
+    // no clean rotation, fall back to the usual weekday and the average gap
+    let mut weekdays: HashMap<Weekday, usize> = HashMap::new();
+    for date in dates {
+        *weekdays.entry(date.weekday()).or_default() += 1;
+    }
+    let (weekday, weekday_count) = weekdays
+        .into_iter()
+        .max_by_key(|(_, count)| *count)
+        .unwrap();
+
+    let average_gap = gaps.iter().sum::<i64>() / gaps.len() as i64;
+    let mut date = last + chrono::Duration::days(average_gap.max(1));
+    while date <= after || date.weekday() != weekday {
+        date += chrono::Duration::days(1);
+    }
+
+    // how often it actually lands on that weekday, halved since there's no real rotation
+    let confidence = weekday_count as f32 / dates.len() as f32 * 0.5 * sample_factor;
+
+    Some(Prediction {
+        name,
+        date,
+        confidence,
+        pattern: format!(
+            "about every {} days, usually on {}",
+            average_gap,
+            weekday_name(weekday)
+        ),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::project;
+    use chrono::NaiveDate;
+
+    fn date(month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2026, month, day).unwrap()
+    }
+
+    #[test]
+    fn finds_a_two_week_rotation() {
+        let dates = [date(9, 4), date(9, 18), date(10, 2)];
+
+        let prediction = project("Pizza".to_owned(), &dates, date(9, 30)).unwrap();
+
+        assert_eq!(prediction.date, date(10, 16));
+        assert_eq!(prediction.pattern, "every 2 weeks on Friday");
+    }
+
+    #[test]
+    fn steps_past_the_horizon() {
+        let dates = [date(9, 1), date(9, 8), date(9, 15)];
+
+        let prediction = project("Tacos".to_owned(), &dates, date(9, 29)).unwrap();
+
+        assert_eq!(prediction.date, date(10, 6));
+        assert_eq!(prediction.pattern, "every Tuesday");
+    }
+
+    #[test]
+    fn falls_back_to_the_average_gap() {
+        // 8, 10 and 9 days apart, so no weekly rotation fits
+        let dates = [date(9, 7), date(9, 15), date(9, 25), date(10, 4)];
+
+        let prediction = project("Soup".to_owned(), &dates, date(9, 30)).unwrap();
+
+        assert!(prediction.pattern.starts_with("about every 9 days"));
+        assert!(prediction.confidence < 0.5);
+    }
+
+    #[test]
+    fn needs_three_servings() {
+        let dates = [date(9, 4), date(9, 18)];
+
+        assert!(project("Pizza".to_owned(), &dates, date(9, 30)).is_none());
+    }
+}
@@ -0,0 +1,149 @@
+use crate::config::{self, BucketConfig, Command};
+use once_cell::sync::Lazy;
+use serenity::model::prelude::{ChannelId, UserId};
+use std::{collections::HashMap, sync::Mutex, time::Instant};
+
+/// forget about idle buckets once there are this many
+const PRUNE_AFTER: usize = 1000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum Key {
+    User(u64),
+    Channel(u64),
+    Search(u64),
+}
+
+/// A token bucket: it holds up to `burst` tokens, refills at `per_minute`
+/// and every request takes one.
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn full(limit: &BucketConfig) -> Self {
+        Bucket {
+            tokens: f64::from(limit.burst),
+            updated: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self, limit: &BucketConfig, now: Instant) {
+        let minutes = now.duration_since(self.updated).as_secs_f64() / 60.0;
+        self.tokens = (self.tokens + minutes * limit.per_minute).min(f64::from(limit.burst));
+        self.updated = now;
+    }
+}
+
+static BUCKETS: Lazy<Mutex<HashMap<Key, Bucket>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Takes a token from the user's and the channel's buckets (and the user's
+/// search bucket for searches). Returns false, without taking anything, if
+/// any of them is empty.
+pub fn allow(user: UserId, channel: ChannelId, command: Command) -> bool {
+    let config = config::get();
+    let limits = &config.rate_limit;
+    if !limits.enabled {
+        return true;
+    }
+
+    let mut keys = vec![
+        (Key::User(user.get()), &limits.user),
+        (Key::Channel(channel.get()), &limits.channel),
+    ];
+    if command == Command::Search {
+        keys.push((Key::Search(user.get()), &limits.search));
+    }
+
+    let now = Instant::now();
+    let mut buckets = BUCKETS.lock().unwrap();
+
+    if buckets.len() > PRUNE_AFTER {
+        // a bucket that has been idle long enough to refill is the same as a new one
+        buckets.retain(|key, bucket| {
+            let limit = match key {
+                Key::User(_) => &limits.user,
+                Key::Channel(_) => &limits.channel,
+                Key::Search(_) => &limits.search,
+            };
+            bucket.refill(limit, now);
+            bucket.tokens < f64::from(limit.burst)
+        });
+    }
+
+    for (key, limit) in &keys {
+        let bucket = buckets.entry(*key).or_insert_with(|| Bucket::full(limit));
+        bucket.refill(limit, now);
+
+        if bucket.tokens < 1.0 {
+            return false;
+        }
+    }
+
+    for (key, _) in &keys {
+        if let Some(bucket) = buckets.get_mut(key) {
+            bucket.tokens -= 1.0;
+        }
+    }
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Bucket;
+    use crate::config::BucketConfig;
+    use std::time::{Duration, Instant};
+
+    const LIMIT: BucketConfig = BucketConfig {
+        burst: 3,
+        per_minute: 6.0,
+    };
+
+    #[test]
+    fn starts_full() {
+        assert_eq!(Bucket::full(&LIMIT).tokens, 3.0);
+    }
+
+    #[test]
+    fn refills_at_the_rate() {
+        let start = Instant::now();
+        let mut bucket = Bucket {
+            tokens: 0.0,
+            updated: start,
+        };
+
+        // 6 a minute is one every 10 seconds
+        bucket.refill(&LIMIT, start + Duration::from_secs(10));
+        assert!((bucket.tokens - 1.0).abs() < 1e-9);
+
+        bucket.refill(&LIMIT, start + Duration::from_secs(15));
+        assert!((bucket.tokens - 1.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn never_holds_more_than_the_burst() {
+        let start = Instant::now();
+        let mut bucket = Bucket {
+            tokens: 2.0,
+            updated: start,
+        };
+
+        bucket.refill(&LIMIT, start + Duration::from_secs(600));
+        assert_eq!(bucket.tokens, 3.0);
+    }
+
+    #[test]
+    fn an_empty_bucket_waits_for_a_whole_token() {
+        let start = Instant::now();
+        let mut bucket = Bucket::full(&LIMIT);
+        bucket.updated = start;
+        bucket.tokens -= 3.0;
+
+        bucket.refill(&LIMIT, start + Duration::from_secs(5));
+        assert!(bucket.tokens < 1.0);
+
+        bucket.refill(&LIMIT, start + Duration::from_secs(10));
+        assert!(bucket.tokens >= 1.0 - 1e-9);
+    }
+}
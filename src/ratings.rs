@@ -0,0 +1,279 @@
+use crate::config::{self, Command};
+use crate::flikisdining::FlikIsDiningMenuItem;
+use crate::reply::{self, Reply};
+use crate::store::JsonStore;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use serenity::{
+    all::{
+        ButtonStyle, ComponentInteraction, ComponentInteractionDataKind, CreateActionRow,
+        CreateButton, CreateInteractionResponse, CreateInteractionResponseMessage,
+        CreateSelectMenu, CreateSelectMenuKind, CreateSelectMenuOption,
+    },
+    builder::CreateEmbed,
+    model::prelude::{GuildId, Message},
+    prelude::Context,
+};
+use std::collections::HashMap;
+use tracing::error;
+
+/// custom id of the "rate a dish" select menu on lunch embeds
+const SELECT_ID: &str = "rate";
+
+/// star buttons are `rate:<food id>:<stars>`
+const BUTTON_PREFIX: &str = "rate:";
+
+/// how many dishes the leaderboard shows
+const LEADERBOARD_SIZE: usize = 10;
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+struct Ratings {
+    /// dish names, keyed by food id
+    names: HashMap<u64, String>,
+
+    /// stars, keyed by guild id, then food id, then user id
+    guilds: HashMap<u64, HashMap<u64, HashMap<u64, u8>>>,
+}
+
+static RATINGS: Lazy<JsonStore<Ratings>> = Lazy::new(|| JsonStore::open("ratings"));
+
+/// ratings from DMs don't have a guild, so they're kept under 0
+fn guild_key(guild: Option<GuildId>) -> u64 {
+    guild.map(|guild| guild.get()).unwrap_or(0)
+}
+
+fn food_key(id: f32) -> u64 {
+    id as u64
+}
+
+fn stars(count: u8) -> String {
+    "⭐".repeat(count as usize)
+}
+
+/// Saves the names of the dishes on a menu, so ratings can be shown by name later.
+pub fn remember(menu: &[FlikIsDiningMenuItem]) {
+    let foods = menu
+        .iter()
+        .filter_map(|item| item.food.as_ref())
+        .filter(|food| RATINGS.read(|ratings| !ratings.names.contains_key(&food_key(food.id))))
+        .map(|food| (food_key(food.id), food.name.clone()))
+        .collect::<Vec<(u64, String)>>();
+
+    if !foods.is_empty() {
+        RATINGS.update(|ratings| ratings.names.extend(foods));
+    }
+}
+
+/// The average rating and number of ratings for a dish in a guild.
+pub fn average(guild: Option<GuildId>, food_id: f32) -> Option<(f32, usize)> {
+    RATINGS.read(|ratings| {
+        let votes = ratings
+            .guilds
+            .get(&guild_key(guild))?
+            .get(&food_key(food_id))?;
+
+        if votes.is_empty() {
+            return None;
+        }
+
+        let total: u32 = votes.values().map(|stars| u32::from(*stars)).sum();
+        Some((total as f32 / votes.len() as f32, votes.len()))
+    })
+}
+
+/// The "rate a dish" dropdown that goes under a lunch embed.
+pub fn components(menu: &[FlikIsDiningMenuItem]) -> Vec<CreateActionRow> {
+    let mut seen: Vec<u64> = Vec::new();
+    let options = menu
+        .iter()
+        .filter_map(|item| item.food.as_ref())
+        .filter(|food| {
+            // select menu values have to be unique
+            let id = food_key(food.id);
+            if seen.contains(&id) {
+                return false;
+            }
+            seen.push(id);
+            true
+        })
+        .take(25)
+        .map(|food| {
+            let label = food.name.chars().take(100).collect::<String>();
+            CreateSelectMenuOption::new(label, food_key(food.id).to_string())
+        })
+        .collect::<Vec<CreateSelectMenuOption>>();
+
+    if options.is_empty() {
+        return vec![];
+    }
+
+    vec![CreateActionRow::SelectMenu(
+        CreateSelectMenu::new(SELECT_ID, CreateSelectMenuKind::String { options })
+            .placeholder("⭐ Rate a dish"),
+    )]
+}
+
+/// Whether this component interaction belongs to the ratings.
+pub fn is_rating_component(component: &ComponentInteraction) -> bool {
+    component.data.custom_id == SELECT_ID || component.data.custom_id.starts_with(BUTTON_PREFIX)
+}
+
+/// Handles the dish dropdown and the star buttons.
+pub async fn handle_component(context: Context, component: ComponentInteraction) {
+    let response = if component.data.custom_id == SELECT_ID {
+        // a dish was picked, ask for the stars (only the person who picked it sees this)
+        let ComponentInteractionDataKind::StringSelect { values } = &component.data.kind else {
+            return;
+        };
+        let Some(food_id) = values.first().and_then(|value| value.parse::<u64>().ok()) else {
+            return;
+        };
+
+        let name = RATINGS.read(|ratings| ratings.names.get(&food_id).cloned());
+        let buttons = (1..=5)
+            .map(|count| {
+                CreateButton::new(format!("{}{}:{}", BUTTON_PREFIX, food_id, count))
+                    .label(stars(count))
+                    .style(ButtonStyle::Secondary)
+            })
+            .collect();
+
+        CreateInteractionResponse::Message(
+            CreateInteractionResponseMessage::new()
+                .content(format!(
+                    "How was **{}**?",
+                    name.unwrap_or("that".to_owned())
+                ))
+                .components(vec![CreateActionRow::Buttons(buttons)])
+                .ephemeral(true),
+        )
+    } else {
+        // a star button was clicked, save the rating
+        let Some((food_id, count)) = component
+            .data
+            .custom_id
+            .strip_prefix(BUTTON_PREFIX)
+            .and_then(|rest| rest.split_once(':'))
+            .and_then(|(food_id, count)| {
+                Some((food_id.parse::<u64>().ok()?, count.parse::<u8>().ok()?))
+            })
+            .filter(|(_, count)| (1..=5).contains(count))
+        else {
+            return;
+        };
+
+        let guild = guild_key(component.guild_id);
+        let user = component.user.id.get();
+        let name = RATINGS.update(|ratings| {
+            ratings
+                .guilds
+                .entry(guild)
+                .or_default()
+                .entry(food_id)
+                .or_default()
+                .insert(user, count);
+
+            ratings.names.get(&food_id).cloned()
+        });
+
+        CreateInteractionResponse::UpdateMessage(
+            CreateInteractionResponseMessage::new()
+                .content(format!(
+                    "You gave **{}** {}",
+                    name.unwrap_or("that".to_owned()),
+                    stars(count)
+                ))
+                .components(vec![]),
+        )
+    };
+
+    if let Err(why) = component.create_response(&context.http, response).await {
+        error!("Error responding to rating: {:?}", why);
+    }
+}
+
+pub async fn handle(context: Context, msg: Message) {
+    // ignore bots
+    if msg.author.bot {
+        return;
+    }
+
+    // get the message content so we can match on it
+    let content = msg.content.to_lowercase();
+
+    // the default trigger captures "top" or "worst", custom ones can say "worst" after the trigger
+    let worst = config::get()
+        .argument(Command::Ratings, &content)
+        .is_some_and(|argument| argument.split_whitespace().next() == Some("worst"));
+
+    // average every rated dish in this guild
+    let mut dishes = RATINGS.read(|ratings| {
+        ratings
+            .guilds
+            .get(&guild_key(msg.guild_id))
+            .map(|dishes| {
+                dishes
+                    .iter()
+                    .filter(|(_, votes)| !votes.is_empty())
+                    .map(|(food_id, votes)| {
+                        let total: u32 = votes.values().map(|stars| u32::from(*stars)).sum();
+                        (
+                            ratings
+                                .names
+                                .get(food_id)
+                                .cloned()
+                                .unwrap_or(format!("#{}", food_id)),
+                            total as f32 / votes.len() as f32,
+                            votes.len(),
+                        )
+                    })
+                    .collect::<Vec<(String, f32, usize)>>()
+            })
+            .unwrap_or_default()
+    });
+
+    // best (or worst) average first, more votes breaks ties
+    dishes.sort_by(|a, b| {
+        let by_average = if worst {
+            a.1.total_cmp(&b.1)
+        } else {
+            b.1.total_cmp(&a.1)
+        };
+
+        by_average.then(b.2.cmp(&a.2))
+    });
+
+    let description = if dishes.is_empty() {
+        "Nobody has rated anything yet. Use the dropdown under a lunch to rate a dish!".to_owned()
+    } else {
+        dishes
+            .into_iter()
+            .take(LEADERBOARD_SIZE)
+            .enumerate()
+            .map(|(idx, (name, average, votes))| {
+                format!(
+                    "{}) **{}** - ⭐ {:.1} ({} rating{})",
+                    idx + 1,
+                    name,
+                    average,
+                    votes,
+                    if votes == 1 { "" } else { "s" }
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    };
+
+    let embed = CreateEmbed::new()
+        .title(if worst {
+            "🤢 Worst Lunches"
+        } else {
+            "🏆 Top Lunches"
+        })
+        .description(description)
+        .color(0xEE8B2F);
+
+    if let Err(why) = reply::send(&context, &msg, Reply::new().embed(embed)).await {
+        error!("Error sending message: {:?}", why);
+    }
+}
@@ -0,0 +1,412 @@
+use crate::flikisdining::{self, FlikIsDiningDay, FoodTag};
+use crate::reply::{self, Reply};
+use crate::{lunch, school};
+use ab_glyph::{point, Font, FontRef, PxScale, ScaleFont};
+use chrono::NaiveDate;
+use once_cell::sync::Lazy;
+use serenity::{builder::CreateAttachment, model::prelude::Message, prelude::Context};
+use tiny_skia::{Color, Paint, Pixmap, PremultipliedColorU8, Rect, Transform};
+use tracing::error;
+
+// DejaVu Sans is bundled so rendering works the same on any machine (see assets/DejaVu-LICENSE)
+static REGULAR: Lazy<FontRef<'static>> = Lazy::new(|| {
+    FontRef::try_from_slice(include_bytes!("../assets/DejaVuSans.ttf"))
+        .expect("bundled font should parse")
+});
+static BOLD: Lazy<FontRef<'static>> = Lazy::new(|| {
+    FontRef::try_from_slice(include_bytes!("../assets/DejaVuSans-Bold.ttf"))
+        .expect("bundled font should parse")
+});
+
+const PADDING: f32 = 16.0;
+const TITLE_HEIGHT: f32 = 56.0;
+const HEADER_HEIGHT: f32 = 40.0;
+const STATION_WIDTH: f32 = 150.0;
+const DAY_WIDTH: f32 = 230.0;
+const CELL_PADDING: f32 = 10.0;
+const ITEM_GAP: f32 = 10.0;
+
+const NAME_SIZE: f32 = 15.0;
+const DETAIL_SIZE: f32 = 12.0;
+const LINE_HEIGHT: f32 = 19.0;
+const DETAIL_HEIGHT: f32 = 18.0;
+const LEGEND_HEIGHT: f32 = 36.0;
+
+const BACKGROUND: (u8, u8, u8) = (0xFF, 0xFF, 0xFF);
+const ACCENT: (u8, u8, u8) = (0xEE, 0x8B, 0x2F);
+const HEADER: (u8, u8, u8) = (0xFC, 0xEB, 0xDA);
+const STRIPE: (u8, u8, u8) = (0xF6, 0xF6, 0xF6);
+const GRID: (u8, u8, u8) = (0xDD, 0xDD, 0xDD);
+const TEXT: (u8, u8, u8) = (0x22, 0x22, 0x22);
+const MUTED: (u8, u8, u8) = (0x77, 0x77, 0x77);
+const DIETARY: (u8, u8, u8) = (0x3A, 0x8F, 0x4B);
+const ALLERGEN: (u8, u8, u8) = (0xC2, 0x5B, 0x1D);
+
+/// one food, laid out for a cell
+struct Entry {
+    lines: Vec<String>,
+    bold: bool,
+    calories: Option<f32>,
+    tags: Vec<FoodTag>,
+}
+
+impl Entry {
+    fn height(&self) -> f32 {
+        self.lines.len() as f32 * LINE_HEIGHT + DETAIL_HEIGHT
+    }
+}
+
+fn color((r, g, b): (u8, u8, u8)) -> Color {
+    Color::from_rgba8(r, g, b, 0xFF)
+}
+
+fn text_width(font: &FontRef, size: f32, text: &str) -> f32 {
+    let scaled = font.as_scaled(PxScale::from(size));
+    let mut width = 0.0;
+    let mut last = None;
+
+    for c in text.chars() {
+        let id = scaled.glyph_id(c);
+        if let Some(last) = last {
+            width += scaled.kern(last, id);
+        }
+        width += scaled.h_advance(id);
+        last = Some(id);
+    }
+
+    width
+}
+
+/// Splits text into lines no wider than `max_width`, breaking on spaces.
+fn wrap(font: &FontRef, size: f32, text: &str, max_width: f32) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    let mut line = String::new();
+
+    for word in text.split_whitespace() {
+        let candidate = if line.is_empty() {
+            word.to_owned()
+        } else {
+            format!("{} {}", line, word)
+        };
+
+        if line.is_empty() || text_width(font, size, &candidate) <= max_width {
+            line = candidate;
+        } else {
+            lines.push(std::mem::replace(&mut line, word.to_owned()));
+        }
+    }
+
+    if !line.is_empty() {
+        lines.push(line);
+    }
+
+    lines
+}
+
+struct Canvas {
+    pixmap: Pixmap,
+}
+
+impl Canvas {
+    fn rect(&mut self, x: f32, y: f32, width: f32, height: f32, fill: (u8, u8, u8)) {
+        let Some(rect) = Rect::from_xywh(x, y, width, height) else {
+            return;
+        };
+
+        let mut paint = Paint::default();
+        paint.set_color(color(fill));
+        self.pixmap
+            .fill_rect(rect, &paint, Transform::identity(), None);
+    }
+
+    /// Draws text with its baseline at `y` and returns how wide it was.
+    fn text(
+        &mut self,
+        font: &FontRef,
+        size: f32,
+        x: f32,
+        y: f32,
+        text: &str,
+        fill: (u8, u8, u8),
+    ) -> f32 {
+        let scaled = font.as_scaled(PxScale::from(size));
+        let (width, height) = (self.pixmap.width() as i32, self.pixmap.height() as i32);
+        let mut caret = x;
+        let mut last = None;
+
+        for c in text.chars() {
+            let id = scaled.glyph_id(c);
+            if let Some(last) = last {
+                caret += scaled.kern(last, id);
+            }
+
+            let glyph = id.with_scale_and_position(size, point(caret, y));
+            caret += scaled.h_advance(id);
+            last = Some(id);
+
+            let Some(outline) = font.outline_glyph(glyph) else {
+                continue;
+            };
+
+            let bounds = outline.px_bounds();
+            let pixels = self.pixmap.pixels_mut();
+
+            outline.draw(|gx, gy, coverage| {
+                let px = bounds.min.x as i32 + gx as i32;
+                let py = bounds.min.y as i32 + gy as i32;
+                if px < 0 || py < 0 || px >= width || py >= height {
+                    return;
+                }
+
+                // blend onto the (always opaque) background
+                let pixel = &mut pixels[(py * width + px) as usize];
+                let alpha = coverage.clamp(0.0, 1.0);
+                let mix = |src: u8, dst: u8| {
+                    (src as f32 * alpha + dst as f32 * (1.0 - alpha)).round() as u8
+                };
+
+                if let Some(blended) = PremultipliedColorU8::from_rgba(
+                    mix(fill.0, pixel.red()),
+                    mix(fill.1, pixel.green()),
+                    mix(fill.2, pixel.blue()),
+                    0xFF,
+                ) {
+                    *pixel = blended;
+                }
+            });
+        }
+
+        caret - x
+    }
+
+    /// Draws a small filled badge with white text and returns how wide it was.
+    fn badge(&mut self, x: f32, y: f32, text: &str, fill: (u8, u8, u8)) -> f32 {
+        let width = text_width(&BOLD, 10.0, text) + 8.0;
+        self.rect(x, y - 11.0, width, 14.0, fill);
+        self.text(&BOLD, 10.0, x + 4.0, y, text, (0xFF, 0xFF, 0xFF));
+        width
+    }
+}
+
+/// Renders a week of menus as a PNG grid, days across the top and stations
+/// down the side, with calories and allergen/dietary badges on every item.
+pub fn render_week(days: &[FlikIsDiningDay]) -> Result<Vec<u8>, String> {
+    let days = days
+        .iter()
+        .filter(|day| !day.menu_items.is_empty())
+        .collect::<Vec<&FlikIsDiningDay>>();
+
+    if days.is_empty() {
+        return Err("There's no lunch this week".to_owned());
+    }
+
+    // stations in the order they first show up
+    let mut stations: Vec<String> = Vec::new();
+    for item in days.iter().flat_map(|day| &day.menu_items) {
+        let station = item.station.clone().unwrap_or_else(|| "Menu".to_owned());
+        if !stations.contains(&station) {
+            stations.push(station);
+        }
+    }
+
+    // lay out every cell, [station][day]
+    let text_width_max = DAY_WIDTH - CELL_PADDING * 2.0;
+    let cells = stations
+        .iter()
+        .map(|station| {
+            days.iter()
+                .map(|day| {
+                    day.menu_items
+                        .iter()
+                        .filter(|item| item.station.as_deref().unwrap_or("Menu") == station)
+                        .filter_map(|item| item.food.as_ref().map(|food| (item.bold, food)))
+                        .map(|(bold, food)| Entry {
+                            lines: wrap(
+                                if bold { &BOLD } else { &REGULAR },
+                                NAME_SIZE,
+                                &food.name,
+                                text_width_max,
+                            ),
+                            bold,
+                            calories: food
+                                .rounded_nutrition_info
+                                .as_ref()
+                                .and_then(|info| info.calories),
+                            tags: food.tags(),
+                        })
+                        .collect::<Vec<Entry>>()
+                })
+                .collect::<Vec<Vec<Entry>>>()
+        })
+        .collect::<Vec<Vec<Vec<Entry>>>>();
+
+    let row_heights = cells
+        .iter()
+        .map(|row| {
+            row.iter()
+                .map(|entries| {
+                    entries.iter().map(Entry::height).sum::<f32>()
+                        + ITEM_GAP * entries.len().saturating_sub(1) as f32
+                })
+                .fold(0.0, f32::max)
+                + CELL_PADDING * 2.0
+        })
+        .collect::<Vec<f32>>();
+
+    let width = PADDING * 2.0 + STATION_WIDTH + DAY_WIDTH * days.len() as f32;
+    let height = PADDING * 2.0
+        + TITLE_HEIGHT
+        + HEADER_HEIGHT
+        + row_heights.iter().sum::<f32>()
+        + LEGEND_HEIGHT;
+
+    let pixmap = Pixmap::new(width.ceil() as u32, height.ceil() as u32)
+        .ok_or_else(|| "Week image is too big".to_owned())?;
+    let mut canvas = Canvas { pixmap };
+    canvas.pixmap.fill(color(BACKGROUND));
+
+    // title
+    let first = NaiveDate::parse_from_str(&days[0].date, "%Y-%m-%d").ok();
+    let title = first
+        .map(|date| format!("Lunch for the week of {}", date.format("%b %-d")))
+        .unwrap_or_else(|| "Lunch this week".to_owned());
+    canvas.rect(0.0, 0.0, width, 6.0, ACCENT);
+    canvas.text(&BOLD, 24.0, PADDING, PADDING + 30.0, &title, TEXT);
+
+    // day headers
+    let grid_top = PADDING + TITLE_HEIGHT;
+    let grid_left = PADDING + STATION_WIDTH;
+    canvas.rect(
+        PADDING,
+        grid_top,
+        width - PADDING * 2.0,
+        HEADER_HEIGHT,
+        HEADER,
+    );
+
+    for (i, day) in days.iter().enumerate() {
+        let label = NaiveDate::parse_from_str(&day.date, "%Y-%m-%d")
+            .map(|date| date.format("%A %-m/%-d").to_string())
+            .unwrap_or(day.date.clone());
+        let x = grid_left + DAY_WIDTH * i as f32 + CELL_PADDING;
+        canvas.text(&BOLD, 15.0, x, grid_top + 26.0, &label, TEXT);
+    }
+
+    // rows
+    let mut y = grid_top + HEADER_HEIGHT;
+    for (row, (station, row_height)) in stations.iter().zip(&row_heights).enumerate() {
+        if row % 2 == 1 {
+            canvas.rect(PADDING, y, width - PADDING * 2.0, *row_height, STRIPE);
+        }
+
+        let mut station_y = y + CELL_PADDING + NAME_SIZE;
+        for line in wrap(&BOLD, 13.0, station, STATION_WIDTH - CELL_PADDING * 2.0) {
+            canvas.text(
+                &BOLD,
+                13.0,
+                PADDING + CELL_PADDING,
+                station_y,
+                &line,
+                ACCENT,
+            );
+            station_y += LINE_HEIGHT;
+        }
+
+        for (column, entries) in cells[row].iter().enumerate() {
+            let x = grid_left + DAY_WIDTH * column as f32 + CELL_PADDING;
+            let mut entry_y = y + CELL_PADDING;
+
+            for entry in entries {
+                let font: &FontRef = if entry.bold { &BOLD } else { &REGULAR };
+                for line in &entry.lines {
+                    entry_y += LINE_HEIGHT;
+                    canvas.text(font, NAME_SIZE, x, entry_y - 4.0, line, TEXT);
+                }
+
+                entry_y += DETAIL_HEIGHT;
+                let mut detail_x = x;
+                if let Some(calories) = entry.calories {
+                    detail_x += canvas.text(
+                        &REGULAR,
+                        DETAIL_SIZE,
+                        x,
+                        entry_y - 4.0,
+                        &format!("{} cal", calories),
+                        MUTED,
+                    ) + 6.0;
+                }
+
+                for tag in &entry.tags {
+                    let fill = if tag.is_allergen() { ALLERGEN } else { DIETARY };
+                    detail_x += canvas.badge(detail_x, entry_y - 4.0, tag.code(), fill) + 3.0;
+                }
+
+                entry_y += ITEM_GAP;
+            }
+        }
+
+        y += row_height;
+        canvas.rect(PADDING, y - 1.0, width - PADDING * 2.0, 1.0, GRID);
+    }
+
+    // column lines
+    for i in 0..days.len() {
+        let x = grid_left + DAY_WIDTH * i as f32;
+        canvas.rect(x, grid_top, 1.0, y - grid_top, GRID);
+    }
+
+    // legend for whichever badges were used
+    let mut x = PADDING;
+    for tag in FoodTag::ALL {
+        let used = cells
+            .iter()
+            .flatten()
+            .flatten()
+            .any(|entry| entry.tags.contains(&tag));
+        if !used {
+            continue;
+        }
+
+        let fill = if tag.is_allergen() { ALLERGEN } else { DIETARY };
+        x += canvas.badge(x, y + 26.0, tag.code(), fill) + 4.0;
+        x += canvas.text(&REGULAR, DETAIL_SIZE, x, y + 26.0, tag.label(), MUTED) + 14.0;
+    }
+
+    canvas
+        .pixmap
+        .encode_png()
+        .map_err(|why| format!("Failed to encode week image: {}", why))
+}
+
+pub async fn handle(context: Context, msg: Message) {
+    // ignore bots
+    if msg.author.bot {
+        return;
+    }
+
+    // get the message content so we can match on it
+    let content = msg.content.to_lowercase();
+    let (_, date) = lunch::resolve_date(&content);
+
+    let week = flikisdining::fetch_school_week(&school::for_user(msg.author.id), date).await;
+    let image = match week.map(|week| week.days) {
+        Ok(days) => tokio::task::spawn_blocking(move || render_week(&days))
+            .await
+            .unwrap_or_else(|why| Err(format!("Failed to render week image: {:?}", why))),
+        Err(why) => Err(why.report()),
+    };
+
+    let message = match image {
+        Ok(image) => Reply::new()
+            .content(format!(
+                "🗓️ Lunch for the week of {}",
+                date.format("%b %-d")
+            ))
+            .add_file(CreateAttachment::bytes(image, "week.png")),
+        Err(why) => Reply::new().content(why),
+    };
+
+    if let Err(why) = reply::send(&context, &msg, message).await {
+        error!("Error sending message: {:?}", why);
+    }
+}
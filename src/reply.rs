@@ -0,0 +1,241 @@
+use crate::{config, metrics};
+use once_cell::sync::Lazy;
+use serenity::{
+    builder::{CreateActionRow, CreateAttachment, CreateEmbed, CreateMessage, EditMessage},
+    model::prelude::{ChannelId, Message, MessageId},
+    prelude::Context,
+};
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use tracing::{instrument, warn, Span};
+
+/// A reply to a command, which can be sent as a new message or edited into an
+/// old one when the command is re-run. Mirrors the parts of `CreateMessage`
+/// the commands use.
+#[derive(Default)]
+pub struct Reply {
+    content: Option<String>,
+    embed: Option<CreateEmbed>,
+    components: Vec<CreateActionRow>,
+    file: Option<CreateAttachment>,
+}
+
+impl Reply {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn content(mut self, content: impl Into<String>) -> Self {
+        self.content = Some(content.into());
+        self
+    }
+
+    pub fn embed(mut self, embed: CreateEmbed) -> Self {
+        self.embed = Some(embed);
+        self
+    }
+
+    pub fn components(mut self, components: Vec<CreateActionRow>) -> Self {
+        self.components = components;
+        self
+    }
+
+    pub fn add_file(mut self, file: CreateAttachment) -> Self {
+        self.file = Some(file);
+        self
+    }
+
+    fn create(self) -> CreateMessage {
+        let mut message = CreateMessage::new().components(self.components);
+
+        if let Some(content) = self.content {
+            message = message.content(content);
+        }
+        if let Some(embed) = self.embed {
+            message = message.embed(embed);
+        }
+        if let Some(file) = self.file {
+            message = message.add_file(file);
+        }
+
+        message
+    }
+
+    /// Replaces everything in the old message, so nothing from the last run is left.
+    fn edit(self) -> EditMessage {
+        let mut message = EditMessage::new()
+            .content(self.content.unwrap_or_default())
+            .embeds(self.embed.into_iter().collect())
+            .components(self.components)
+            .remove_all_attachments();
+
+        if let Some(file) = self.file {
+            message = message.new_attachment(file);
+        }
+
+        message
+    }
+}
+
+/// the replies sent for a request message
+struct Tracked {
+    channel: ChannelId,
+    replies: Vec<MessageId>,
+    /// when the request was first answered, edits after the window are ignored
+    since: Instant,
+}
+
+static REPLIES: Lazy<Mutex<HashMap<MessageId, Tracked>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// a request that's being re-run after an edit
+struct Rerun {
+    since: Instant,
+    /// the old replies, reused in the order they were sent
+    previous: VecDeque<MessageId>,
+}
+
+static RERUNS: Lazy<Mutex<HashMap<MessageId, Rerun>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+fn window() -> Duration {
+    Duration::from_secs(config::get().reply_window_minutes * 60)
+}
+
+fn track(request: &Message, reply: MessageId) {
+    let window = window();
+    let since = RERUNS
+        .lock()
+        .unwrap()
+        .get(&request.id)
+        .map(|rerun| rerun.since)
+        .unwrap_or_else(Instant::now);
+
+    let mut replies = REPLIES.lock().unwrap();
+
+    // nothing older than the window can be edited anymore
+    replies.retain(|_, tracked| tracked.since.elapsed() < window);
+
+    replies
+        .entry(request.id)
+        .or_insert_with(|| Tracked {
+            channel: request.channel_id,
+            replies: Vec::new(),
+            since,
+        })
+        .replies
+        .push(reply);
+}
+
+/// Sends a reply to a request message and remembers it, so it can be edited if
+/// the request is edited, or deleted if it's deleted. While the request is
+/// being re-run, this edits the old replies instead of sending new ones.
+#[instrument(skip_all, fields(edit))]
+pub async fn send(context: &Context, request: &Message, reply: Reply) -> serenity::Result<Message> {
+    let previous = RERUNS
+        .lock()
+        .unwrap()
+        .get_mut(&request.id)
+        .and_then(|rerun| rerun.previous.pop_front());
+
+    Span::current().record("edit", previous.is_some());
+
+    let (action, sent) = match previous {
+        Some(id) => (
+            "edit",
+            request
+                .channel_id
+                .edit_message(&context.http, id, reply.edit())
+                .await,
+        ),
+        None => (
+            "send",
+            request
+                .channel_id
+                .send_message(&context.http, reply.create())
+                .await,
+        ),
+    };
+
+    let sent = sent.inspect_err(|_| metrics::SEND_FAILURES.with_label_values(&[action]).inc())?;
+
+    track(request, sent.id);
+    Ok(sent)
+}
+
+/// Whether a message was answered recently enough for edits to matter.
+pub fn is_tracked(request: MessageId) -> bool {
+    REPLIES
+        .lock()
+        .unwrap()
+        .get(&request)
+        .is_some_and(|tracked| tracked.since.elapsed() < window())
+}
+
+/// Gets ready to run an edited request again, so its replies get edited.
+/// Returns false if there's nothing to update.
+pub fn begin_rerun(request: MessageId) -> bool {
+    let Some(tracked) = REPLIES.lock().unwrap().remove(&request) else {
+        return false;
+    };
+
+    if tracked.since.elapsed() >= window() {
+        return false;
+    }
+
+    RERUNS.lock().unwrap().insert(
+        request,
+        Rerun {
+            since: tracked.since,
+            previous: tracked.replies.into(),
+        },
+    );
+    true
+}
+
+/// Deletes whatever old replies the re-run didn't need, e.g. all of them if
+/// the edited message isn't a command anymore.
+pub async fn finish_rerun(context: &Context, channel: ChannelId, request: MessageId) {
+    let leftover = RERUNS.lock().unwrap().remove(&request);
+
+    for id in leftover.into_iter().flat_map(|rerun| rerun.previous) {
+        if let Err(why) = channel.delete_message(&context.http, id).await {
+            warn!("Error deleting reply: {:?}", why);
+        }
+    }
+}
+
+/// Ends a re-run that was turned away before replying, keeping the old
+/// replies and tracking them as before.
+pub fn cancel_rerun(channel: ChannelId, request: MessageId) {
+    let Some(rerun) = RERUNS.lock().unwrap().remove(&request) else {
+        return;
+    };
+
+    REPLIES.lock().unwrap().insert(
+        request,
+        Tracked {
+            channel,
+            replies: rerun.previous.into(),
+            since: rerun.since,
+        },
+    );
+}
+
+/// Deletes the replies to a request that was deleted.
+pub async fn delete(context: &Context, request: MessageId) {
+    let Some(tracked) = REPLIES.lock().unwrap().remove(&request) else {
+        return;
+    };
+
+    if tracked.since.elapsed() >= window() {
+        return;
+    }
+
+    for id in tracked.replies {
+        if let Err(why) = tracked.channel.delete_message(&context.http, id).await {
+            warn!("Error deleting reply: {:?}", why);
+        }
+    }
+}
@@ -0,0 +1,97 @@
+use crate::config::{self, Command, SchoolConfig};
+use crate::reply::{self, Reply};
+use crate::store::JsonStore;
+use once_cell::sync::Lazy;
+use serenity::{
+    builder::CreateEmbed,
+    model::prelude::{Message, UserId},
+    prelude::Context,
+};
+use std::collections::HashMap;
+use tracing::error;
+
+/// the school each user picked, by name in the config's `[schools]`, keyed by discord user id
+static SAVED: Lazy<JsonStore<HashMap<u64, String>>> = Lazy::new(|| JsonStore::open("schools"));
+
+/// The school a user's menus come from: the one they saved, if it's still in
+/// the config, otherwise the default school.
+pub fn for_user(user: UserId) -> SchoolConfig {
+    let config = config::get();
+
+    SAVED
+        .read(|saved| saved.get(&user.get()).cloned())
+        .and_then(|name| config.schools.get(&name).cloned())
+        .unwrap_or_else(|| config.school.clone())
+}
+
+/// the configured schools as a list, or a note that there aren't any
+fn list() -> String {
+    let config = config::get();
+
+    if config.schools.is_empty() {
+        return "There are no other schools set up, everyone gets the default school.".to_owned();
+    }
+
+    config
+        .schools
+        .iter()
+        .map(|(name, school)| format!("• `{}` ({})", name, school.slug))
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+pub async fn handle(context: Context, msg: Message) {
+    // ignore bots
+    if msg.author.bot {
+        return;
+    }
+
+    // get the message content so we can match on it
+    let content = msg.content.to_lowercase();
+
+    // find the content after the school trigger ("school")
+    let name = config::get()
+        .argument(Command::School, &content)
+        .unwrap_or_default();
+    let name = name.as_str();
+
+    let user = msg.author.id.get();
+    let config = config::get();
+
+    let reply = match name {
+        "" => {
+            let current = SAVED
+                .read(|saved| saved.get(&user).cloned())
+                .filter(|name| config.schools.contains_key(name))
+                .unwrap_or("default".to_owned());
+
+            format!(
+                "**Your school:** {}\n\nPick one with `school <name>`, or `school default`:\n{}",
+                current,
+                list()
+            )
+        }
+        "default" => {
+            SAVED.update(|saved| saved.remove(&user));
+            "Using the default school.".to_owned()
+        }
+        name if config.schools.contains_key(name) => {
+            SAVED.update(|saved| saved.insert(user, name.to_owned()));
+            format!("Saved `{}` as your school.", name)
+        }
+        name => format!(
+            "I don't know a school called `{}`. Try one of these:\n{}",
+            name,
+            list()
+        ),
+    };
+
+    let embed = CreateEmbed::new()
+        .title("🏫 School")
+        .description(reply)
+        .color(0xEE8B2F);
+
+    if let Err(why) = reply::send(&context, &msg, Reply::new().embed(embed)).await {
+        error!("Error sending message: {:?}", why);
+    }
+}
@@ -1,19 +1,27 @@
-use crate::env::PRIMARY_LUNCH_CHANNEL;
-use crate::flikisdining;
-use chrono::{DateTime, Utc};
+use crate::config::{self, Command, SchoolConfig};
+use crate::flikisdining::{self, FoodTag};
+use crate::metrics;
+use crate::predict::{self, Prediction};
+use crate::reply::{self, Reply};
+use crate::school;
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::Serialize;
 use serenity::{
-    all::{CreateEmbedFooter, CreateMessage},
-    builder::CreateEmbed,
-    model::prelude::Message,
-    prelude::Context,
+    all::CreateEmbedFooter, builder::CreateEmbed, model::prelude::Message, prelude::Context,
+};
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
 };
 use tantivy::{
     doc,
-    query::QueryParser,
-    schema::{Field, Schema, Value},
-    Index, TantivyDocument,
+    query::{AllQuery, BooleanQuery, Occur, Query, QueryParser, TermQuery},
+    schema::{Field, IndexRecordOption, Schema, Value},
+    Index, TantivyDocument, Term,
 };
+use thiserror::Error;
 use tokio::task::JoinSet;
+use tracing::{debug, error, field, info, info_span, instrument, warn, Instrument};
 
 // static TANTIVY_SCHEMA: Schema = {
 //     let mut schema = tantivy::schema::Schema::builder();
@@ -26,17 +34,329 @@ use tokio::task::JoinSet;
 //     schema.build()
 // };
 
-fn create_index() -> (tantivy::Index, Schema, Field, Field) {
+/// how many weeks ahead to search
+const SEARCH_WEEKS: i64 = 3;
+
+/// A menu item that matched a search
+#[derive(Serialize, Clone, Debug)]
+pub struct SearchResult {
+    pub name: String,
+    /// yyyy-mm-dd
+    pub date: String,
+    pub tags: Vec<FoodTag>,
+    pub score: f32,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct SearchResults {
+    /// the search text, without any dietary filters
+    pub query: String,
+    pub results: Vec<SearchResult>,
+    /// a guess from the menu history, only when nothing published matched
+    pub prediction: Option<Prediction>,
+    /// things that went wrong along the way, results may be missing entries
+    pub warnings: Vec<String>,
+}
+
+#[derive(Error, Debug)]
+pub enum SearchError {
+    #[error("Couldn't understand `{0}`")]
+    InvalidQuery(String),
+
+    #[error(transparent)]
+    IndexFailed(#[from] tantivy::TantivyError),
+}
+
+/// words that don't narrow anything down once a tag filter is given,
+/// e.g. the "entrees" in "when will we have vegan entrees"
+const GENERIC_WORDS: [&str; 14] = [
+    "entree", "entrees", "entrée", "entrées", "food", "foods", "option", "options", "item",
+    "items", "meal", "meals", "dish", "dishes",
+];
+
+fn create_index() -> (tantivy::Index, Schema, Field, Field, Field) {
     // create the schema
     let mut schema = tantivy::schema::Schema::builder();
     let content = schema.add_text_field("content", tantivy::schema::TEXT | tantivy::schema::STORED);
     let date = schema.add_date_field("date", tantivy::schema::STORED);
+    let tags = schema.add_text_field("tags", tantivy::schema::TEXT | tantivy::schema::STORED);
     let schema = schema.build();
 
     // create the index
     let index = Index::create_in_ram(schema.clone());
 
-    return (index, schema, content, date);
+    (index, schema, content, date, tags)
+}
+
+/// Pulls dietary filters out of a search term. Returns the remaining search
+/// text, the tags results must have and the tags they must not have.
+///
+/// "vegan entrees" requires the vegan tag, while "nut free", "nut-free",
+/// "no nuts" and "without nuts" exclude anything tagged with nuts. Only the
+/// diet words themselves become required tags, so "fish sticks" and "veggie
+/// burger" are searched by name like any other dish.
+fn parse_filters(search_term: &str) -> (String, Vec<FoodTag>, Vec<FoodTag>) {
+    let normalized = search_term
+        .replace("gluten free", "glutenfree")
+        .replace("gluten-free", "glutenfree")
+        .replace("-free", " free");
+
+    let words = normalized.split_whitespace().collect::<Vec<&str>>();
+    let mut remaining: Vec<&str> = Vec::new();
+    let mut required: Vec<FoodTag> = Vec::new();
+    let mut excluded: Vec<FoodTag> = Vec::new();
+
+    let mut i = 0;
+    while i < words.len() {
+        let word = words[i];
+
+        // "no nuts" / "without nuts"
+        if (word == "no" || word == "without") && i + 1 < words.len() {
+            if let Some(tag) = FoodTag::from_word(words[i + 1]).filter(FoodTag::is_allergen) {
+                excluded.push(tag);
+                i += 2;
+                continue;
+            }
+        }
+
+        if let Some(tag) = FoodTag::from_word(word) {
+            // "nut free"
+            if words.get(i + 1) == Some(&"free") && tag.is_allergen() {
+                excluded.push(tag);
+                i += 2;
+                continue;
+            }
+
+            // "vegan", "vegetarian", "glutenfree" and "halal"
+            if !tag.is_allergen() && word == tag.key() {
+                required.push(tag);
+                i += 1;
+                continue;
+            }
+        }
+
+        remaining.push(word);
+        i += 1;
+    }
+
+    // drop the filler words if there's a filter doing the work
+    if !required.is_empty() || !excluded.is_empty() {
+        remaining.retain(|word| !GENERIC_WORDS.contains(word));
+    }
+
+    (remaining.join(" "), required, excluded)
+}
+
+/// how many dishes the last search indexed, and when
+static LAST_INDEX: Mutex<Option<(usize, DateTime<Utc>)>> = Mutex::new(None);
+
+/// The size of the last search index and when it was built, `None` before the first search.
+pub fn last_index() -> Option<(usize, DateTime<Utc>)> {
+    *LAST_INDEX.lock().unwrap()
+}
+
+/// Searches the next few weeks of a school's menus. Dietary words in the search
+/// term become filters (see [`parse_filters`]), and if nothing published matches
+/// the menu history (only kept for the default school) is asked for a prediction.
+#[instrument(skip_all, fields(term = search_term, school = school.slug))]
+pub async fn search(
+    school: &SchoolConfig,
+    search_term: &str,
+) -> Result<SearchResults, SearchError> {
+    // save start time so we can calculate processing time
+    let start = Utc::now();
+
+    // split out any dietary filters
+    let (search_text, required_tags, excluded_tags) = parse_filters(search_term);
+
+    // search the next 3 weeks
+    let (index, _schema, content, date, tags) = create_index();
+    let mut index_writer = index.writer(15_000_000)?;
+
+    // fetch 3 weeks of lunch
+    let mut set = JoinSet::new();
+    let mut warnings: Vec<String> = Vec::new();
+    let mut failed_documents = 0;
+
+    // the last day with a published menu, predictions have to land after this
+    let mut horizon = config::get().today();
+
+    // time spent adding to the index, separate from waiting on the fetches
+    let index_span = info_span!("index", documents = field::Empty);
+    let mut index_time = Duration::ZERO;
+    let mut documents: usize = 0;
+
+    for n in 0..SEARCH_WEEKS {
+        let date = Utc::now() + chrono::Duration::weeks(n);
+        let school = school.clone();
+        set.spawn(
+            async move {
+                flikisdining::fetch_school_week(&school, date)
+                    .await
+                    .map(|week| week.days)
+            }
+            .in_current_span(),
+        );
+    }
+
+    while let Some(res) = set.join_next().await {
+        let week = match res {
+            Ok(Ok(week)) => week,
+            Ok(Err(why)) => {
+                warn!("Error fetching lunch: {:?}", why);
+                warnings.push(format!("failed to fetch a week of lunch: {}", why));
+                continue;
+            }
+            Err(why) => {
+                warn!("Error fetching lunch: {:?}", why);
+                warnings.push(format!("failed to fetch a week of lunch: {}", why));
+                continue;
+            }
+        };
+
+        let indexing = Instant::now();
+        index_span.in_scope(|| {
+            week.into_iter().for_each(|day| {
+                let date_value = day.date;
+
+                if let Ok(day_date) = NaiveDate::parse_from_str(&date_value, "%Y-%m-%d") {
+                    if !day.menu_items.is_empty() && day_date > horizon {
+                        horizon = day_date;
+                    }
+                }
+
+                day.menu_items.into_iter().for_each(|food| {
+                    let food = food.food.unwrap();
+                    let tag_keys = food
+                        .tags()
+                        .iter()
+                        .map(FoodTag::key)
+                        .collect::<Vec<&str>>()
+                        .join(" ");
+
+                    if let Err(why) = index_writer.add_document(doc!(
+                        content => food.name,
+                        date => date_value.clone(),
+                        tags => tag_keys
+                    )) {
+                        warn!("Error adding document: {:?}", why);
+                        failed_documents += 1;
+                    } else {
+                        documents += 1;
+                    }
+                })
+            })
+        });
+        index_time += indexing.elapsed();
+    }
+
+    if failed_documents > 0 {
+        warnings.push(format!(
+            "failed to add {} document(s) to index",
+            failed_documents
+        ));
+    }
+
+    // commit the index so we can search it
+    index_span.record("documents", documents);
+    let committing = Instant::now();
+    if let Err(why) = index_span.in_scope(|| index_writer.commit()) {
+        warn!("Error committing index: {:?}", why);
+        warnings.push(format!("failed to commit index: {}", why));
+    }
+    drop(index_span);
+    metrics::INDEX_SECONDS.observe((index_time + committing.elapsed()).as_secs_f64());
+    *LAST_INDEX.lock().unwrap() = Some((documents, Utc::now()));
+
+    let committed_time = Utc::now();
+    debug!(
+        "Committed index ({:?} ms)",
+        (Utc::now() - start).num_milliseconds()
+    );
+
+    // search the index
+    let reader = index.reader()?;
+    let searcher = reader.searcher();
+    let query_parser = QueryParser::for_index(&index, vec![content]);
+
+    // the text part of the query, or everything if only filters were given
+    let text_query: Box<dyn Query> = if search_text.is_empty() {
+        Box::new(AllQuery)
+    } else {
+        query_parser.parse_query(&search_text).map_err(|why| {
+            debug!("Error parsing query: {:?}", why);
+            SearchError::InvalidQuery(search_text.clone())
+        })?
+    };
+
+    let mut clauses: Vec<(Occur, Box<dyn Query>)> = vec![(Occur::Must, text_query)];
+    for (occur, tag) in required_tags
+        .iter()
+        .map(|tag| (Occur::Must, tag))
+        .chain(excluded_tags.iter().map(|tag| (Occur::MustNot, tag)))
+    {
+        clauses.push((
+            occur,
+            Box::new(TermQuery::new(
+                Term::from_field_text(tags, tag.key()),
+                IndexRecordOption::Basic,
+            )),
+        ));
+    }
+
+    let query = BooleanQuery::new(clauses);
+    let top_docs = searcher
+        .search(&query, &tantivy::collector::TopDocs::with_limit(10))
+        .unwrap_or(vec![]);
+
+    debug!(
+        "Searched index ({:?} ms)",
+        (Utc::now() - committed_time).num_milliseconds()
+    );
+
+    // read the matching documents back out
+    let results = top_docs
+        .into_iter()
+        .filter_map(|(score, doc_address)| {
+            let retrieved_doc: TantivyDocument = searcher
+                .doc(doc_address)
+                .inspect_err(|why| warn!("Error retrieving document: {:?}", why))
+                .ok()?;
+
+            let text = |field: Field| {
+                retrieved_doc
+                    .get_first(field)
+                    .and_then(|value| value.as_str())
+                    .map(str::to_owned)
+            };
+
+            Some(SearchResult {
+                name: text(content)?,
+                date: text(date)?,
+                tags: text(tags)
+                    .unwrap_or_default()
+                    .split_whitespace()
+                    .filter_map(FoodTag::from_word)
+                    .collect(),
+                score,
+            })
+        })
+        .collect::<Vec<SearchResult>>();
+
+    // nothing published, see if the menu history has a guess
+    let prediction =
+        if results.is_empty() && !search_text.is_empty() && *school == config::get().school {
+            predict::predict(&search_text, horizon)
+        } else {
+            None
+        };
+
+    Ok(SearchResults {
+        query: search_text,
+        results,
+        prediction,
+        warnings,
+    })
 }
 
 pub async fn handle(context: Context, msg: Message) {
@@ -51,157 +371,163 @@ pub async fn handle(context: Context, msg: Message) {
     // get the message content so we can match on it
     let content = msg.content.to_lowercase();
 
-    // check if starts with "when will we have"
-    if msg.channel_id.to_string() == *PRIMARY_LUNCH_CHANNEL
-        && content.starts_with("when will we have")
-    {
-        // find the content after "when will we have"
-        let search_term = content
-            .split("when will we have")
-            .collect::<Vec<&str>>()
-            .get(1)
-            .unwrap_or(&"")
-            .trim();
-
-        println!("Searching for lunch: {}", search_term);
+    // find the content after the search trigger ("when will we have")
+    if let Some(search_term) = config::get().argument(Command::Search, &content) {
+        info!("Searching for lunch: {}", search_term);
 
         // if nothing, return
         if search_term.is_empty() {
             return;
         }
 
-        // search the next 3 weeks
-        let (index, _schema, content, date) = create_index();
-        let mut index_writer = index.writer(15_000_000).unwrap();
-
-        // fetch 3 weeks of lunch
-        let mut set = JoinSet::new();
-
-        for n in 0..3 {
-            let date = DateTime::<Utc>::from(Utc::now() + chrono::Duration::weeks(n));
-            set.spawn(flikisdining::fetch_week_lunch(date));
-        }
-
-        while let Some(res) = set.join_next().await {
-            if let Err(why) = res {
-                println!("Error fetching lunch: {:?}", why);
-
-                // attempt to send in channel
-                let _ = msg
-                        .channel_id
-                        .say(&context.http, format!("[warn] failed to fetch a week of lunch, result may be missing entries: {:?}", why)).await;
-
-                continue;
-            }
-
-            res.unwrap().into_iter().for_each(|week| {
-                    week.into_iter().for_each(|day| {
-                        let date_value = day.date;
-
-                        day.menu_items.into_iter().for_each(|food| {
-                            if let Err(why) = index_writer.add_document(doc!(
-                                content => food.food.unwrap().name,
-                                date => date_value.clone()
-                            )) {
-                                println!("Error adding document: {:?}", why);
-
-                                // attempt to send in channel
-                                let _ = msg
-                                    .channel_id
-                                    .say(&context.http, format!("[warn] failed to add document to index, result may be missing entries: {:?}", why));
-                            }
-                        })
-                    })
-                })
-        }
-
-        // commit the index so we can search it
-        if let Err(why) = index_writer.commit() {
-            println!("Error committing index: {:?}", why);
-
-            // attempt to send in channel
-            let _ = msg
-                .channel_id
-                .say(
-                    &context.http,
-                    format!(
-                        "[warn] failed to commit index, result may be missing entries: {:?}",
-                        why
-                    ),
+        let search = match search(&school::for_user(msg.author.id), &search_term).await {
+            Ok(search) => search,
+            Err(why) => {
+                error!("Error searching: {:?}", why);
+
+                let _ = reply::send(
+                    &context,
+                    &msg,
+                    Reply::new().content(match why {
+                        SearchError::InvalidQuery(_) => why.to_string(),
+                        _ => format!("Search failed: {}", why),
+                    }),
                 )
                 .await;
-        }
-
-        let committed_time = Utc::now();
-        println!(
-            "Committed index ({:?} ms)",
-            (Utc::now() - start).num_milliseconds()
-        );
 
-        // search the index
-        let reader = index.reader().unwrap();
-        let searcher = reader.searcher();
-        let query_parser = QueryParser::for_index(&index, vec![content]);
-
-        let query = query_parser.parse_query(search_term).unwrap();
-        let top_docs = searcher
-            .search(&query, &tantivy::collector::TopDocs::with_limit(10))
-            .unwrap_or(vec![]);
-
-        println!(
-            "Searched index ({:?} ms)",
-            (Utc::now() - committed_time).num_milliseconds()
-        );
+                return;
+            }
+        };
+
+        // let the channel know if the results might be incomplete
+        for warning in &search.warnings {
+            let _ = reply::send(
+                &context,
+                &msg,
+                Reply::new().content(format!("[warn] {}, result may be missing entries", warning)),
+            )
+            .await;
+        }
 
-        // format the top documents
-        let description = top_docs
-            .into_iter()
-            .enumerate()
-            .map(|(idx, (score, doc_address))| {
-                // get the document
-                let retrieved_doc = searcher.doc(doc_address);
+        let embed = if let Some(prediction) = search.prediction {
+            let date = config::get().midday(prediction.date);
+
+            CreateEmbed::default()
+                .title("🔮 Prediction")
+                .description(format!(
+                    "`{}` isn't on any published menu, but based on past menus **{}** might be back around <t:{}:D>.\n> Pattern: {}\n> Confidence: {} ({:.0}%)",
+                    search.query,
+                    prediction.name,
+                    date.timestamp(),
+                    prediction.pattern,
+                    prediction.confidence_label(),
+                    prediction.confidence * 100.0
+                ))
+                .color(0x9C27B0)
+                .footer(CreateEmbedFooter::new(
+                    "This is a prediction, not the published menu • ".to_owned()
+                        + &(Utc::now() - start).num_milliseconds().to_string()
+                        + " ms",
+                ))
+        } else {
+            // format the results
+            let description = if search.results.is_empty() {
+                format!("Nothing found in the next {} weeks", SEARCH_WEEKS)
+            } else {
+                search
+                    .results
+                    .iter()
+                    .enumerate()
+                    .map(|(idx, result)| {
+                        let Some(date) = config::get().parse_date(&result.date) else {
+                            return format!("{}: Error parsing document", idx);
+                        };
+
+                        format!(
+                            "{}) **{}** {}\n> <t:{}:F>\n> Score: {}",
+                            idx,
+                            result.name,
+                            result.tags.iter().map(FoodTag::emoji).collect::<String>(),
+                            date.timestamp(),
+                            result.score
+                        )
+                    })
+                    .collect::<Vec<String>>()
+                    .join("\n")
+            };
+
+            CreateEmbed::default()
+                .title("🔍 Search Results")
+                .description(description)
+                .color(0x00FF00)
+                .footer(CreateEmbedFooter::new(
+                    (Utc::now() - start).num_milliseconds().to_string() + " ms",
+                ))
+        };
 
-                if retrieved_doc.is_err() {
-                    return format!("{}: Error retrieving document", idx);
-                }
+        // now send the embed
+        let message = Reply::new().embed(embed);
+        let _ = reply::send(&context, &msg, message).await;
+    }
+}
 
-                let retrieved_doc: TantivyDocument = retrieved_doc.unwrap();
+#[cfg(test)]
+mod tests {
+    use super::parse_filters;
+    use crate::flikisdining::FoodTag;
+
+    #[test]
+    fn parse_filters_requires_tags() {
+        let (text, required, excluded) = parse_filters("vegan entrees");
+        assert_eq!(text, "");
+        assert_eq!(required, vec![FoodTag::Vegan]);
+        assert!(excluded.is_empty());
+    }
 
-                // get the content
-                let content = retrieved_doc.get_first(content).unwrap().as_str();
-                let date = retrieved_doc.get_first(date).unwrap().as_str();
+    #[test]
+    fn parse_filters_excludes_free_of_tags() {
+        for term in [
+            "nut free pizza",
+            "nut-free pizza",
+            "no nuts pizza",
+            "pizza without nuts",
+        ] {
+            let (text, required, excluded) = parse_filters(term);
+            assert_eq!(text, "pizza", "{}", term);
+            assert!(required.is_empty(), "{}", term);
+            assert_eq!(excluded, vec![FoodTag::TreeNuts], "{}", term);
+        }
+    }
 
-                if content.is_none() || date.is_none() {
-                    return format!("{}: Error parsing document", idx);
-                }
+    #[test]
+    fn parse_filters_keeps_gluten_free_as_a_tag() {
+        let (text, required, excluded) = parse_filters("gluten free pasta");
+        assert_eq!(text, "pasta");
+        assert_eq!(required, vec![FoodTag::GlutenFree]);
+        assert!(excluded.is_empty());
+    }
 
-                // parse the date (yyyy-mm-dd) and set to midday EST
-                let date =
-                    DateTime::parse_from_rfc3339(&(date.unwrap().to_owned() + "T12:00:00-05:00"))
-                        .unwrap()
-                        .with_timezone(&Utc);
-
-                format!(
-                    "{}) **{}**\n> <t:{}:F>\n> Score: {}",
-                    idx,
-                    content.unwrap(),
-                    (date.timestamp_millis() / 1000),
-                    score
-                )
-            })
-            .collect::<Vec<String>>()
-            .join("\n");
+    #[test]
+    fn parse_filters_searches_food_words_by_name() {
+        for term in [
+            "fish sticks",
+            "egg rolls",
+            "pulled pork sandwich",
+            "veggie burger",
+            "fish",
+        ] {
+            let (text, required, excluded) = parse_filters(term);
+            assert_eq!(text, term);
+            assert!(required.is_empty(), "{}", term);
+            assert!(excluded.is_empty(), "{}", term);
+        }
+    }
 
-        // now send the embed
-        let embed = CreateEmbed::default()
-            .title("🔍 Search Results")
-            .description(description)
-            .color(0x00FF00)
-            .footer(CreateEmbedFooter::new(
-                (Utc::now() - start).num_milliseconds().to_string() + " ms",
-            ));
-
-        let message = CreateMessage::new().embed(embed);
-        let _ = msg.channel_id.send_message(&context.http, message).await;
+    #[test]
+    fn parse_filters_leaves_other_words() {
+        let (text, required, excluded) = parse_filters("eggplant parmesan");
+        assert_eq!(text, "eggplant parmesan");
+        assert!(required.is_empty());
+        assert!(excluded.is_empty());
     }
 }
@@ -0,0 +1,34 @@
+use tracing::{error, info};
+
+/// Waits for SIGINT (ctrl-c) or, on unix, SIGTERM.
+pub async fn signal() {
+    let interrupt = async {
+        if let Err(why) = tokio::signal::ctrl_c().await {
+            error!("Failed to listen for SIGINT: {:?}", why);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(why) => {
+                error!("Failed to listen for SIGTERM: {:?}", why);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = interrupt => info!("Got SIGINT, shutting down"),
+        _ = terminate => info!("Got SIGTERM, shutting down"),
+    }
+}
@@ -0,0 +1,224 @@
+use crate::config::{self, Command};
+use crate::history::{self, weekday_name, HistoryItem};
+use crate::reply::{self, Reply};
+use crate::school;
+use chrono::{Datelike, NaiveDate, Utc, Weekday};
+use serenity::{
+    all::CreateEmbedFooter, builder::CreateEmbed, model::prelude::Message, prelude::Context,
+};
+use std::collections::{BTreeMap, HashMap};
+use tracing::error;
+
+/// how many entrées the whole-menu view lists
+const TOP_ENTREES: usize = 10;
+
+fn format_date(date: NaiveDate) -> String {
+    date.format("%a, %b %-d %Y").to_string()
+}
+
+/// Stats for a single dish.
+fn dish_stats(days: &BTreeMap<NaiveDate, Vec<HistoryItem>>, dish: &str) -> CreateEmbed {
+    let words = dish.split_whitespace().collect::<Vec<&str>>();
+    let today = config::get().today();
+
+    // every date the dish was served, and the most common name it went by
+    let mut dates: Vec<NaiveDate> = Vec::new();
+    let mut names: HashMap<&str, usize> = HashMap::new();
+    for (date, items) in days {
+        let matching = items.iter().find(|item| {
+            let name = item.name.to_lowercase();
+            words.iter().all(|word| name.contains(word))
+        });
+
+        if let Some(item) = matching {
+            dates.push(*date);
+            *names.entry(&item.name).or_default() += 1;
+        }
+    }
+
+    let Some(name) = names
+        .into_iter()
+        .max_by_key(|(_, count)| *count)
+        .map(|(name, _)| name)
+    else {
+        return CreateEmbed::new()
+            .title("📊 Stats")
+            .description(format!("`{}` isn't in the menu history", dish));
+    };
+
+    // the usual weekday
+    let mut weekdays: HashMap<Weekday, usize> = HashMap::new();
+    for date in &dates {
+        *weekdays.entry(date.weekday()).or_default() += 1;
+    }
+    let (weekday, weekday_count) = weekdays
+        .into_iter()
+        .max_by_key(|(_, count)| *count)
+        .unwrap();
+
+    let longest_gap = dates
+        .windows(2)
+        .map(|pair| (pair[1] - pair[0]).num_days())
+        .max();
+
+    let last = dates.iter().rev().find(|date| **date <= today);
+    let next = dates.iter().find(|date| **date > today);
+
+    // how much of the history this covers
+    let first_day = *days.keys().next().unwrap();
+    let last_day = *days.keys().next_back().unwrap();
+    let weeks = ((last_day - first_day).num_days() / 7).max(1);
+
+    CreateEmbed::new()
+        .title(format!("📊 {}", name))
+        .field(
+            "Served",
+            format!(
+                "{} time{} in {} week{} of menus",
+                dates.len(),
+                if dates.len() == 1 { "" } else { "s" },
+                weeks,
+                if weeks == 1 { "" } else { "s" }
+            ),
+            true,
+        )
+        .field(
+            "Usual day",
+            format!(
+                "{} ({} of {})",
+                weekday_name(weekday),
+                weekday_count,
+                dates.len()
+            ),
+            true,
+        )
+        .field(
+            "Longest gap",
+            longest_gap
+                .map(|gap| format!("{} days", gap))
+                .unwrap_or("-".to_owned()),
+            true,
+        )
+        .field(
+            "Last served",
+            last.map(|date| format_date(*date))
+                .unwrap_or("-".to_owned()),
+            true,
+        )
+        .field(
+            "Next served",
+            next.map(|date| format_date(*date))
+                .unwrap_or("not published yet".to_owned()),
+            true,
+        )
+}
+
+/// Stats for the whole menu.
+fn menu_stats(days: &BTreeMap<NaiveDate, Vec<HistoryItem>>) -> CreateEmbed {
+    // entrées are the bold items, if the school bolds anything
+    let has_bold = days.values().flatten().any(|item| item.bold);
+
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for item in days.values().flatten() {
+        if item.bold || !has_bold {
+            *counts.entry(&item.name).or_default() += 1;
+        }
+    }
+
+    let mut counts = counts.into_iter().collect::<Vec<(&str, usize)>>();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+
+    let entrees = counts
+        .into_iter()
+        .take(TOP_ENTREES)
+        .enumerate()
+        .map(|(idx, (name, count))| format!("{}) **{}** - {}x", idx + 1, name, count))
+        .collect::<Vec<String>>()
+        .join("\n");
+
+    // total calories of everything on the menu, averaged over the days that list any
+    let daily_calories = days
+        .values()
+        .map(|items| items.iter().filter_map(|item| item.calories).sum::<f32>())
+        .filter(|calories| *calories > 0.0)
+        .collect::<Vec<f32>>();
+    let average_calories = if daily_calories.is_empty() {
+        "-".to_owned()
+    } else {
+        format!(
+            "{:.0} cals",
+            daily_calories.iter().sum::<f32>() / daily_calories.len() as f32
+        )
+    };
+
+    let first_day = *days.keys().next().unwrap();
+    let last_day = *days.keys().next_back().unwrap();
+
+    CreateEmbed::new()
+        .title("📊 Menu Stats")
+        .description(format!(
+            "{} days of menus, {} to {}",
+            days.len(),
+            format_date(first_day),
+            format_date(last_day)
+        ))
+        .field(
+            if has_bold {
+                "Most common entrées"
+            } else {
+                "Most common items"
+            },
+            entrees,
+            false,
+        )
+        .field(
+            "Average calories per day (all items)",
+            average_calories,
+            false,
+        )
+}
+
+pub async fn handle(context: Context, msg: Message) {
+    // ignore bots
+    if msg.author.bot {
+        return;
+    }
+
+    // save start time so we can calculate processing time
+    let start = Utc::now();
+
+    // get the message content so we can match on it
+    let content = msg.content.to_lowercase();
+
+    // find the content after the stats trigger ("stats")
+    let dish = config::get()
+        .argument(Command::Stats, &content)
+        .unwrap_or_default();
+    let dish = dish.as_str();
+
+    let days = history::days();
+    let embed = if days.is_empty() {
+        CreateEmbed::new()
+            .title("📊 Stats")
+            .description("The menu history is empty, try again once some menus have been fetched")
+    } else if dish.is_empty() {
+        menu_stats(&days)
+    } else {
+        dish_stats(&days, dish)
+    };
+
+    let embed = embed.color(0xEE8B2F).footer(CreateEmbedFooter::new(
+        (Utc::now() - start).num_milliseconds().to_string() + " ms",
+    ));
+
+    // the menu history only records the default school, so say so to anyone
+    // who picked another one
+    let mut message = Reply::new().embed(embed);
+    if school::for_user(msg.author.id) != config::get().school {
+        message = message.content("Stats only cover the default school's menus");
+    }
+
+    if let Err(why) = reply::send(&context, &msg, message).await {
+        error!("Error sending message: {:?}", why);
+    }
+}
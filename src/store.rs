@@ -0,0 +1,141 @@
+use crate::config;
+use chrono::Utc;
+use once_cell::sync::Lazy;
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{Mutex, RwLock},
+};
+use tracing::{error, info, warn};
+
+/// held while a store is being written, so [`flush`] can wait for those to finish
+static SAVING: RwLock<()> = RwLock::new(());
+
+/// stores whose last save failed, with what should have been written, to try again in [`flush`]
+static UNSAVED: Lazy<Mutex<HashMap<PathBuf, Vec<u8>>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// A value that is kept in memory and saved as json in the `data_dir` every time
+/// it changes. Meant for the small bits of per-user state the bot keeps.
+pub struct JsonStore<T> {
+    path: PathBuf,
+    data: RwLock<T>,
+    /// set when the file couldn't be loaded or moved aside, so it's never overwritten
+    read_only: bool,
+}
+
+impl<T> JsonStore<T>
+where
+    T: Serialize + DeserializeOwned + Default,
+{
+    /// Loads `<data_dir>/<name>.json`, starting empty if it doesn't exist yet.
+    /// A file that can't be parsed is renamed to `<name>.json.corrupt-<time>`
+    /// first, so the next save doesn't overwrite it. If it can't be read or
+    /// moved, the store starts empty and never saves.
+    pub fn open(name: &str) -> Self {
+        let path = PathBuf::from(&config::get().data_dir).join(format!("{}.json", name));
+
+        let (data, read_only) = match fs::read_to_string(&path) {
+            Ok(text) => match serde_json::from_str(&text) {
+                Ok(data) => (data, false),
+                Err(why) => {
+                    let corrupt = path.with_extension(format!(
+                        "json.corrupt-{}",
+                        Utc::now().format("%Y%m%dT%H%M%SZ")
+                    ));
+
+                    match fs::rename(&path, &corrupt) {
+                        Ok(()) => {
+                            error!(
+                                "Failed to parse {}, moved it to {} and starting empty: {}",
+                                path.display(),
+                                corrupt.display(),
+                                why
+                            );
+                            (T::default(), false)
+                        }
+                        Err(rename) => {
+                            error!(
+                                "Failed to parse {} ({}) or move it aside ({}), changes won't be saved",
+                                path.display(),
+                                why,
+                                rename
+                            );
+                            (T::default(), true)
+                        }
+                    }
+                }
+            },
+            Err(why) if why.kind() == io::ErrorKind::NotFound => (T::default(), false),
+            Err(why) => {
+                error!(
+                    "Failed to read {}, changes won't be saved: {}",
+                    path.display(),
+                    why
+                );
+                (T::default(), true)
+            }
+        };
+
+        JsonStore {
+            path,
+            data: RwLock::new(data),
+            read_only,
+        }
+    }
+
+    pub fn read<R>(&self, f: impl FnOnce(&T) -> R) -> R {
+        f(&self.data.read().unwrap())
+    }
+
+    /// Changes the value and writes it back to disk.
+    pub fn update<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+        let mut data = self.data.write().unwrap();
+        let result = f(&mut data);
+
+        if self.read_only {
+            warn!("Not saving {}, it couldn't be loaded", self.path.display());
+            return result;
+        }
+
+        let _saving = SAVING.read().unwrap();
+        match serde_json::to_vec_pretty(&*data) {
+            Ok(bytes) => {
+                if let Err(why) = save(&self.path, &bytes) {
+                    error!("Failed to save {}: {}", self.path.display(), why);
+                    UNSAVED.lock().unwrap().insert(self.path.clone(), bytes);
+                } else {
+                    UNSAVED.lock().unwrap().remove(&self.path);
+                }
+            }
+            Err(why) => error!("Failed to serialize {}: {}", self.path.display(), why),
+        }
+
+        result
+    }
+}
+
+fn save(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    // write to a temporary file first so a crash can't leave half a file behind
+    let tmp = path.with_extension("json.tmp");
+    fs::write(&tmp, bytes)?;
+    fs::rename(&tmp, path)
+}
+
+/// Waits for saves in progress and tries once more to save any store whose
+/// last save failed. Called on shutdown, so nothing is lost on the way out.
+pub fn flush() {
+    let _saving = SAVING.write().unwrap();
+
+    for (path, bytes) in UNSAVED.lock().unwrap().drain() {
+        match save(&path, &bytes) {
+            Ok(()) => info!("Saved {} on shutdown", path.display()),
+            Err(why) => error!("Failed to save {} on shutdown: {}", path.display(), why),
+        }
+    }
+}
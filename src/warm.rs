@@ -0,0 +1,83 @@
+use crate::{config, flikisdining};
+use chrono::{Datelike, Duration, Utc, Weekday};
+use std::{
+    iter,
+    sync::atomic::{AtomicBool, Ordering},
+};
+use tokio::task::JoinSet;
+use tracing::{info, info_span, warn, Instrument};
+
+/// so reconnects don't start a second warmer
+static STARTED: AtomicBool = AtomicBool::new(false);
+
+/// How long until `warm_at` on the next school day.
+fn until_next() -> std::time::Duration {
+    let config = config::get();
+    let now = Utc::now().with_timezone(&config.timezone);
+
+    (0..8)
+        .map(|days| now.date_naive() + Duration::days(days))
+        .filter(|date| !matches!(date.weekday(), Weekday::Sat | Weekday::Sun))
+        .filter_map(|date| {
+            date.and_time(config.warm_at)
+                .and_local_timezone(config.timezone)
+                .earliest()
+        })
+        .find(|at| *at > now)
+        .and_then(|at| (at - now).to_std().ok())
+        // can't happen, but check again in an hour rather than spin
+        .unwrap_or(std::time::Duration::from_secs(3600))
+}
+
+/// Refetches this week and next week for every school, skipping the HTTP
+/// cache, so the first people asking for lunch get a fresh menu right away.
+async fn warm() {
+    let config = config::get();
+    let today = config.midday(config.today());
+
+    let mut fetches = JoinSet::new();
+    for school in iter::once(&config.school).chain(config.schools.values()) {
+        for weeks in [0, 1] {
+            let school = school.clone();
+            let date = today + Duration::weeks(weeks);
+
+            fetches.spawn(
+                async move {
+                    if let Err(why) = flikisdining::refetch_school_week(&school, date).await {
+                        warn!(
+                            school = %school.slug,
+                            week = %date.format("%Y-%m-%d"),
+                            error = ?why,
+                            "Error warming the cache"
+                        );
+                    }
+                }
+                .in_current_span(),
+            );
+        }
+    }
+
+    let weeks = fetches.len();
+    fetches.join_all().await;
+
+    info!("Warmed {} weeks", weeks);
+}
+
+/// Starts the background job that refreshes the menus shortly before lunch
+/// on school days. Does nothing if it's already running.
+pub fn start() {
+    if STARTED.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    tokio::spawn(async move {
+        loop {
+            // read every time around, so config reloads apply from the next day on
+            tokio::time::sleep(until_next()).await;
+
+            if config::get().features.cache_warmer {
+                warm().instrument(info_span!("warm")).await;
+            }
+        }
+    });
+}
@@ -0,0 +1,283 @@
+use crate::config::{self, Command, SchoolConfig};
+use crate::reply::{self, Reply};
+use crate::store::JsonStore;
+use crate::{flikisdining, metrics, school};
+use chrono::{NaiveDate, Utc};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use serenity::{
+    builder::{CreateEmbed, CreateMessage},
+    http::Http,
+    model::prelude::{Message, UserId},
+    prelude::Context,
+};
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::task::JoinSet;
+use tracing::{error, info, warn};
+
+/// how many weeks ahead the watcher looks for new menus
+const WATCH_WEEKS: i64 = 3;
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+struct Watchlist {
+    /// watched terms, keyed by discord user id
+    terms: HashMap<u64, Vec<String>>,
+
+    /// `term@yyyy-mm-dd` keys that have already been sent, keyed by discord user id
+    alerted: HashMap<u64, Vec<String>>,
+}
+
+static WATCHLIST: Lazy<JsonStore<Watchlist>> = Lazy::new(|| JsonStore::open("watchlist"));
+
+/// so reconnects don't start a second watcher
+static STARTED: AtomicBool = AtomicBool::new(false);
+
+/// the terms a user is watching
+pub fn terms_for(user: UserId) -> Vec<String> {
+    WATCHLIST.read(|watchlist| {
+        watchlist
+            .terms
+            .get(&user.get())
+            .cloned()
+            .unwrap_or_default()
+    })
+}
+
+/// Starts the background job that DMs users when something they watch shows
+/// up on a newly published menu. Does nothing if it's already running.
+pub fn start(http: Arc<Http>) {
+    if STARTED.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    tokio::spawn(async move {
+        loop {
+            // both are read every time around, so config reloads apply
+            if config::get().features.watch {
+                check(&http).await;
+            }
+
+            let minutes = config::get().watch_interval_minutes;
+            tokio::time::sleep(Duration::from_secs(minutes * 60)).await;
+        }
+    });
+}
+
+/// Looks through the upcoming weeks and alerts anyone watching something on them.
+async fn check(http: &Http) {
+    // everyone watching something
+    let watchers = WATCHLIST.read(|watchlist| {
+        watchlist
+            .terms
+            .iter()
+            .filter(|(_, terms)| !terms.is_empty())
+            .map(|(user, _)| *user)
+            .collect::<Vec<u64>>()
+    });
+
+    // don't bother fetching if nobody is watching anything
+    if watchers.is_empty() {
+        return;
+    }
+
+    // the schools their menus come from, each one only fetched once
+    let mut schools: Vec<SchoolConfig> = Vec::new();
+    let mut school_of: HashMap<u64, usize> = HashMap::new();
+    for user in watchers {
+        let school = school::for_user(UserId::new(user));
+        let index = schools
+            .iter()
+            .position(|known| *known == school)
+            .unwrap_or_else(|| {
+                schools.push(school);
+                schools.len() - 1
+            });
+        school_of.insert(user, index);
+    }
+
+    let today = config::get().today();
+
+    // fetch the upcoming weeks
+    let mut set = JoinSet::new();
+    for (index, school) in schools.iter().enumerate() {
+        for n in 0..WATCH_WEEKS {
+            let school = school.clone();
+            let date = Utc::now() + chrono::Duration::weeks(n);
+            set.spawn(async move { (index, flikisdining::fetch_school_week(&school, date).await) });
+        }
+    }
+
+    // (date, food name) for everything coming up, for each school
+    let mut upcoming: Vec<Vec<(NaiveDate, String)>> = vec![Vec::new(); schools.len()];
+    while let Some(res) = set.join_next().await {
+        match res {
+            Ok((index, Ok(week))) => {
+                for day in week.days {
+                    let Ok(date) = NaiveDate::parse_from_str(&day.date, "%Y-%m-%d") else {
+                        continue;
+                    };
+
+                    if date < today {
+                        continue;
+                    }
+
+                    for item in day.menu_items {
+                        if let Some(food) = item.food {
+                            upcoming[index].push((date, food.name));
+                        }
+                    }
+                }
+            }
+            Ok((_, Err(why))) => warn!("Error fetching lunch for watchlist: {:?}", why),
+            Err(why) => error!("Error joining watchlist fetch: {:?}", why),
+        }
+    }
+
+    for menu in &mut upcoming {
+        menu.sort();
+    }
+
+    // figure out who needs to hear about what, marking it as sent as we go
+    let alerts = WATCHLIST.update(|watchlist| {
+        let mut alerts: Vec<(u64, String)> = Vec::new();
+
+        // forget about days that have passed
+        for keys in watchlist.alerted.values_mut() {
+            keys.retain(|key| {
+                key.rsplit_once('@')
+                    .and_then(|(_, date)| NaiveDate::parse_from_str(date, "%Y-%m-%d").ok())
+                    .is_some_and(|date| date >= today)
+            });
+        }
+
+        for (user, terms) in &watchlist.terms {
+            // someone who started watching since the fetch waits for the next check
+            let Some(upcoming) = school_of.get(user).map(|index| &upcoming[*index]) else {
+                continue;
+            };
+            let alerted = watchlist.alerted.entry(*user).or_default();
+
+            for term in terms {
+                for (date, name) in upcoming {
+                    let key = format!("{}@{}", term, date.format("%Y-%m-%d"));
+
+                    if !name.to_lowercase().contains(term) || alerted.contains(&key) {
+                        continue;
+                    }
+
+                    alerted.push(key);
+                    alerts.push((
+                        *user,
+                        format!("**{}** is on the menu {}", name, date.format("%A, %b %-d")),
+                    ));
+                }
+            }
+        }
+
+        alerts
+    });
+
+    for (user, alert) in alerts {
+        info!("Sending watchlist alert to {}: {}", user, alert);
+
+        let embed = CreateEmbed::new()
+            .title("👀 Watchlist")
+            .description(alert)
+            .color(0xEE8B2F);
+
+        if let Err(why) = UserId::new(user)
+            .direct_message(http, CreateMessage::new().embed(embed))
+            .await
+        {
+            error!("Error sending watchlist alert: {:?}", why);
+            metrics::SEND_FAILURES.with_label_values(&["dm"]).inc();
+        }
+    }
+}
+
+pub async fn handle(context: Context, msg: Message) {
+    // ignore bots
+    if msg.author.bot {
+        return;
+    }
+
+    // get the message content so we can match on it
+    let content = msg.content.to_lowercase();
+    let user = msg.author.id.get();
+
+    // the default trigger keeps the word it matched ("watch", "unwatch" or
+    // "watchlist"), after a custom one "list" and "remove <food>" do the same
+    let argument = config::get()
+        .argument(Command::Watch, &content)
+        .unwrap_or_default();
+    let (verb, rest) = argument.split_once(' ').unwrap_or((&argument, ""));
+
+    let reply = match verb {
+        "watchlist" | "list" => {
+            let terms = terms_for(msg.author.id);
+
+            if terms.is_empty() {
+                "You aren't watching anything. Add something with `watch <food>`.".to_owned()
+            } else {
+                terms
+                    .iter()
+                    .map(|term| format!("• {}", term))
+                    .collect::<Vec<String>>()
+                    .join("\n")
+            }
+        }
+        "unwatch" | "remove" => {
+            let term = rest.trim().to_owned();
+
+            let removed = WATCHLIST.update(|watchlist| {
+                let terms = watchlist.terms.entry(user).or_default();
+                let before = terms.len();
+                terms.retain(|watched| *watched != term);
+                before != terms.len()
+            });
+
+            if removed {
+                format!("Stopped watching `{}`", term)
+            } else {
+                format!("You weren't watching `{}`", term)
+            }
+        }
+        _ => {
+            let term = if verb == "watch" { rest } else { &argument }
+                .trim()
+                .to_owned();
+
+            if term.is_empty() {
+                "Usage: `watch <food>`, `unwatch <food>` or `watchlist`".to_owned()
+            } else {
+                WATCHLIST.update(|watchlist| {
+                    let terms = watchlist.terms.entry(user).or_default();
+                    if !terms.contains(&term) {
+                        terms.push(term.clone());
+                    }
+                });
+
+                format!(
+                    "Watching `{}`, I'll DM you when it shows up on the menu",
+                    term
+                )
+            }
+        }
+    };
+
+    let embed = CreateEmbed::new()
+        .title("👀 Watchlist")
+        .description(reply)
+        .color(0xEE8B2F);
+
+    if let Err(why) = reply::send(&context, &msg, Reply::new().embed(embed)).await {
+        error!("Error sending message: {:?}", why);
+    }
+}